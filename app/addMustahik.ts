@@ -3,6 +3,7 @@ import { Program } from "@coral-xyz/anchor";
 import { Zakachain } from "../target/types/zakachain";
 import { PublicKey, Connection, Keypair } from "@solana/web3.js";
 import * as fs from 'fs';
+import { createHash, randomBytes } from 'crypto';
 
 // Configure the client to use the devnet cluster
 const connection = new Connection("https://api.devnet.solana.com", "confirmed");
@@ -38,18 +39,43 @@ async function main() {
         const mustahikWallet = Keypair.generate();
         console.log("Mustahik Wallet:", mustahikWallet.publicKey.toBase58());
 
-        // Add mustahik
-        console.log("\nAdding mustahik...");
+        // Derive the PDA for the mustahik account
+        const [mustahikPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("mustahik"), statePda.toBuffer(), mustahikWallet.publicKey.toBuffer()],
+            programId
+        );
+        console.log("Mustahik PDA:", mustahikPda.toBase58());
+
+        // The wallet must be registered as a field officer to apply on the mustahik's behalf
+        const [officerPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("officer"), wallet.publicKey.toBuffer()],
+            programId
+        );
+
+        // Only a salted hash of the NIK goes on chain; keep the salt with the off-chain record
+        const nik = process.argv[2];
+        if (!nik) {
+            throw new Error("Usage: ts-node app/addMustahik.ts <NIK>");
+        }
+        const salt = randomBytes(32);
+        const identityHash = createHash("sha256").update(Buffer.concat([salt, Buffer.from(nik)])).digest();
+        console.log("Identity salt (store off-chain):", salt.toString("hex"));
+
+        // Submit mustahik application
+        console.log("\nSubmitting mustahik application...");
         const tx = await program.methods
-            .addMustahik(mustahikWallet.publicKey)
+            .submitApplication(mustahikWallet.publicKey, { fakir: {} }, Array.from(identityHash), "")
             .accounts({
                 state: statePda,
-                amil: wallet.publicKey,
+                applicant: wallet.publicKey,
+                officer: officerPda,
+                mustahik: mustahikPda,
+                systemProgram: anchor.web3.SystemProgram.programId,
             })
             .rpc();
         
         console.log("Transaction signature:", tx);
-        console.log("Mustahik added successfully!");
+        console.log("Application submitted; awaiting survey and approval.");
 
     } catch (error) {
         console.error("Error:", error);
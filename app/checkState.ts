@@ -51,12 +51,15 @@ async function main() {
         
         // Display mustahiks
         console.log("\n=== Mustahiks ===");
-        const mustahiks = (state as any).mustahiks as PublicKey[];
+        const mustahiks = await program.account.mustahik.all([
+            { memcmp: { offset: 8, bytes: stateAccount.toBase58() } },
+        ]);
         if (mustahiks.length === 0) {
             console.log("No mustahiks registered yet");
         } else {
             mustahiks.forEach((mustahik, index) => {
-                console.log(`${index + 1}. ${mustahik.toBase58()}`);
+                const status = Object.keys((mustahik.account as any).status)[0];
+                console.log(`${index + 1}. ${(mustahik.account as any).wallet.toBase58()} (${status})`);
             });
         }
 
@@ -25,6 +25,7 @@ anchor.setProvider(provider);
 // Program ID from your Anchor.toml
 const programId = new PublicKey("3EJSTPJYM3BaNBvL7haWnhXoNh5GvmsQfwL1QQ2am3GJ");
 const program = anchor.workspace.Zakachain as Program<Zakachain>;
+const coreProgramId = new PublicKey("375fwyjaiv8qfFhhBd8kbHnQy5VVKxEBgKMCGDkbxUZm");
 
 // USDC mint address on devnet
 const usdcMint = new PublicKey("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU");
@@ -81,18 +82,51 @@ async function main() {
         const payerAccount = await getAccount(connection, payerTokenAccount);
         console.log("Payer USDC Balance:", Number(payerAccount.amount) / 1e6, "USDC");
 
+        // Shared Hijri calendar on the core program
+        const [hijriCalendarPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("hijri_calendar")],
+            coreProgramId
+        );
+
+        const state = await program.account.zakaChainState.fetch(statePda);
+
+        // Client-generated payment id, reused on retries
+        const paymentId = Array.from(anchor.web3.Keypair.generate().publicKey.toBytes().slice(0, 16));
+        const [paymentPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("payment"), wallet.publicKey.toBuffer(), Buffer.from(paymentId)],
+            programId
+        );
+
+        // Receipt PDA for this payment
+        const [receiptPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("receipt"), wallet.publicKey.toBuffer(), Buffer.from(paymentId), Buffer.from([0])],
+            programId
+        );
+        console.log("Receipt PDA:", receiptPda.toBase58());
+
         // Collect zakat (1 USDC)
         console.log("\nCollecting zakat...");
         const zakatAmount = 1 * 1e6; // 1 USDC with 6 decimals
         const tx = await program.methods
-            .collectZakat(new anchor.BN(zakatAmount))
+            .collectZakat(new anchor.BN(zakatAmount), state.amilFeePercentage, null, paymentId)
             .accounts({
                 state: statePda,
                 payer: wallet.publicKey,
                 payerTokenAccount: payerTokenAccount,
                 programTokenAccount: programTokenAccount,
                 amilTokenAccount: amilTokenAccount,
+                receipt: receiptPda,
+                payment: paymentPda,
+                hijriCalendar: hijriCalendarPda,
+                agent: null,
+                fund: null,
+                obligation: null,
+                profile: null,
+                pool: null,
+                poolVault: null,
+                donorMatch: null,
                 tokenProgram: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
             })
             .rpc();
         
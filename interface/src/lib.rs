@@ -0,0 +1,89 @@
+//! CPI interface for protocols that pay zakat on their users' behalf.
+//!
+//! Integrating programs depend on this crate instead of the program itself and
+//! call [`collect_zakat_for`] from their own instruction, signing as the
+//! protocol authority PDA the amil registered. The address helpers derive the
+//! accounts the instruction expects.
+
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address;
+
+pub use zakachain::cpi::accounts::CollectZakatFor;
+pub use zakachain::cpi::collect_zakat_for;
+pub use zakachain::program::Zakachain;
+pub use zakachain::ID;
+
+/// The program's state PDA, which also owns the program token account.
+pub fn state_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"state"], &ID).0
+}
+
+/// The program vault for `mint`: the state PDA's associated token account.
+/// Collections into any other account are rejected.
+pub fn vault_address(mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(&state_address(), mint)
+}
+
+/// The integration registered for a protocol's authority PDA.
+pub fn integration_address(protocol_authority: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"integration", protocol_authority.as_ref()], &ID).0
+}
+
+/// The receipt issued to `user` for a payment.
+pub fn receipt_address(user: &Pubkey, payment_id: &[u8; 16]) -> Pubkey {
+    Pubkey::find_program_address(&[b"receipt", user.as_ref(), payment_id.as_ref(), &[0]], &ID).0
+}
+
+/// The marker that makes a retried payment fail instead of paying twice.
+pub fn payment_address(user: &Pubkey, payment_id: &[u8; 16]) -> Pubkey {
+    Pubkey::find_program_address(&[b"payment", user.as_ref(), payment_id.as_ref()], &ID).0
+}
+
+/// The shared Hijri calendar on the core program.
+pub fn hijri_calendar_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"hijri_calendar"], &zakachain_core::ID).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{Discriminator, InstructionData};
+
+    #[test]
+    fn addresses_use_the_program_seeds() {
+        let user = Pubkey::new_unique();
+        let payment_id = [7u8; 16];
+        assert_eq!(state_address(), Pubkey::find_program_address(&[b"state"], &zakachain::ID).0);
+        // The state PDA is what the main program signs identity registry calls with
+        assert_eq!(zakachain_core::identity::institution_authority(&zakachain::ID), state_address());
+        let mint = Pubkey::new_unique();
+        assert_eq!(
+            vault_address(&mint),
+            Pubkey::find_program_address(
+                &[state_address().as_ref(), anchor_spl::token::ID.as_ref(), mint.as_ref()],
+                &anchor_spl::associated_token::ID,
+            )
+            .0
+        );
+        assert_ne!(receipt_address(&user, &payment_id), payment_address(&user, &payment_id));
+        assert_ne!(receipt_address(&user, &payment_id), receipt_address(&user, &[8u8; 16]));
+        assert_eq!(
+            hijri_calendar_address(),
+            Pubkey::find_program_address(&[b"hijri_calendar"], &zakachain_core::ID).0
+        );
+    }
+
+    #[test]
+    fn instruction_data_starts_with_the_discriminator() {
+        let data = zakachain::instruction::CollectZakatFor {
+            amount: 1_000_000,
+            max_fee_percentage: 25,
+            payment_id: [1u8; 16],
+        }
+        .data();
+        assert_eq!(&data[..8], &zakachain::instruction::CollectZakatFor::DISCRIMINATOR);
+        assert_eq!(&data[8..16], &1_000_000u64.to_le_bytes());
+        assert_eq!(data[16], 25);
+        assert_eq!(data.len(), 8 + 8 + 1 + 16);
+    }
+}
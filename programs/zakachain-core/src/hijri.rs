@@ -0,0 +1,268 @@
+// Hijri Calendar Module
+//
+// Tabular (arithmetic) Islamic calendar with the common 30-year leap cycle
+// (leap years 2, 5, 7, 10, 13, 16, 18, 21, 24, 26, 29). Month starts can be
+// overridden with amil-published adjustments when rukyat differs from the
+// tabular calendar.
+use anchor_lang::prelude::*;
+
+pub const SECONDS_PER_DAY: i64 = 86400;
+pub const MUHARRAM: u8 = 1;
+pub const RAMADAN: u8 = 9;
+pub const SHAWWAL: u8 = 10;
+pub const DHU_AL_HIJJAH: u8 = 12;
+
+// Days from 1970-01-01 back to 1 Muharram 1 AH (16 July 622, Julian)
+const EPOCH_UNIX_DAY: i64 = -492_148;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MonthStart {
+    pub year: u16,
+    pub month: u8,
+    pub unix_day: i64,
+}
+
+impl MonthStart {
+    pub const LEN: usize = 2 + 1 + 8;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HijriDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl HijriDate {
+    pub fn is_ramadan(&self) -> bool {
+        self.month == RAMADAN
+    }
+
+    /// Ramadan up to and including 1 Shawwal, the last day zakat al-fitr can be paid.
+    pub fn is_fitrah_window(&self) -> bool {
+        self.month == RAMADAN || (self.month == SHAWWAL && self.day == 1)
+    }
+
+    /// Eid al-Adha and the days of tashriq (10-13 Dhu al-Hijjah).
+    pub fn is_eid_al_adha_window(&self) -> bool {
+        self.month == DHU_AL_HIJJAH && (10..=13).contains(&self.day)
+    }
+}
+
+pub fn unix_day(unix_timestamp: i64) -> i64 {
+    unix_timestamp.div_euclid(SECONDS_PER_DAY)
+}
+
+/// First day of a Hijri month on the tabular calendar, as days since the Unix epoch.
+pub fn tabular_month_start(year: u16, month: u8) -> i64 {
+    let year = year as i64;
+    let month = month as i64;
+    EPOCH_UNIX_DAY
+        + (59 * (month - 1) + 1) / 2
+        + (year - 1) * 354
+        + (3 + 11 * year) / 30
+}
+
+/// First day of a Hijri month, preferring a published adjustment over the tabular date.
+pub fn month_start(year: u16, month: u8, adjustments: &[MonthStart]) -> i64 {
+    adjustments
+        .iter()
+        .find(|a| a.year == year && a.month == month)
+        .map(|a| a.unix_day)
+        .unwrap_or_else(|| tabular_month_start(year, month))
+}
+
+fn next_month(year: u16, month: u8) -> (u16, u8) {
+    if month == DHU_AL_HIJJAH {
+        (year + 1, MUHARRAM)
+    } else {
+        (year, month + 1)
+    }
+}
+
+fn previous_month(year: u16, month: u8) -> (u16, u8) {
+    if month == MUHARRAM {
+        (year - 1, DHU_AL_HIJJAH)
+    } else {
+        (year, month - 1)
+    }
+}
+
+fn tabular_from_unix_day(day: i64) -> (u16, u8) {
+    let days = day - EPOCH_UNIX_DAY;
+    let year = ((30 * days + 10646) / 10631) as u16;
+    let day_of_year = day - tabular_month_start(year, MUHARRAM);
+    let month = if day_of_year <= 29 {
+        1
+    } else {
+        (((2 * (day_of_year - 29) + 58) / 59) + 1).min(12)
+    };
+    (year, month as u8)
+}
+
+pub fn from_unix_timestamp(unix_timestamp: i64, adjustments: &[MonthStart]) -> HijriDate {
+    let day = unix_day(unix_timestamp);
+    let (mut year, mut month) = tabular_from_unix_day(day);
+    // Adjustments move month starts by a day or two, so one step either way is enough
+    if day < month_start(year, month, adjustments) {
+        (year, month) = previous_month(year, month);
+    } else {
+        let (next_year, next) = next_month(year, month);
+        if day >= month_start(next_year, next, adjustments) {
+            (year, month) = (next_year, next);
+        }
+    }
+    HijriDate {
+        year,
+        month,
+        day: (day - month_start(year, month, adjustments) + 1) as u8,
+    }
+}
+
+/// Whether `unix_day` as the start of a month keeps that month and the one
+/// before it at 29 or 30 days, given the other published month starts.
+pub fn is_valid_month_start(year: u16, month: u8, unix_day: i64, adjustments: &[MonthStart]) -> bool {
+    let (previous_year, previous) = previous_month(year, month);
+    let (next_year, next) = next_month(year, month);
+    let previous_length = unix_day - month_start(previous_year, previous, adjustments);
+    let length = month_start(next_year, next, adjustments) - unix_day;
+    (29..=30).contains(&previous_length) && (29..=30).contains(&length)
+}
+
+/// Unix timestamp at the start (00:00 UTC) of a Hijri date.
+pub fn to_unix_timestamp(date: HijriDate, adjustments: &[MonthStart]) -> i64 {
+    let day = month_start(date.year, date.month, adjustments) + date.day as i64 - 1;
+    day * SECONDS_PER_DAY
+}
+
+/// The same Hijri date `years` later, e.g. a haul anniversary.
+pub fn add_years(date: HijriDate, years: u16) -> HijriDate {
+    HijriDate {
+        year: date.year + years,
+        ..date
+    }
+}
+
+/// Month and year running totals as they stand on `today`, for totals last
+/// updated in `period_month` of `period_year`. Totals from an earlier month or
+/// year no longer count and read as zero.
+pub fn period_totals(
+    period_year: u16,
+    period_month: u8,
+    month_total: u64,
+    year_total: u64,
+    today: HijriDate,
+) -> (u64, u64) {
+    if period_year != today.year {
+        (0, 0)
+    } else if period_month != today.month {
+        (0, year_total)
+    } else {
+        (month_total, year_total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-11, the first day of Ramadan 1445
+    const RAMADAN_1445: i64 = 19_793;
+
+    fn date(year: u16, month: u8, day: u8) -> HijriDate {
+        HijriDate { year, month, day }
+    }
+
+    // Shawwal 1445 observed a day before the tabular date
+    fn early_shawwal() -> [MonthStart; 1] {
+        [MonthStart {
+            year: 1445,
+            month: SHAWWAL,
+            unix_day: tabular_month_start(1445, SHAWWAL) - 1,
+        }]
+    }
+
+    #[test]
+    fn tabular_known_dates() {
+        assert_eq!(tabular_month_start(1445, RAMADAN), RAMADAN_1445);
+        assert_eq!(
+            from_unix_timestamp(RAMADAN_1445 * SECONDS_PER_DAY, &[]),
+            date(1445, RAMADAN, 1)
+        );
+        // 2024-04-10, 1 Shawwal 1445
+        assert_eq!(from_unix_timestamp(19_823 * SECONDS_PER_DAY + 3600, &[]), date(1445, SHAWWAL, 1));
+        // 1445 is a leap year, so 2024-07-07 is 30 Dhu al-Hijjah
+        assert_eq!(from_unix_timestamp(19_911 * SECONDS_PER_DAY, &[]), date(1445, DHU_AL_HIJJAH, 30));
+        assert_eq!(from_unix_timestamp(19_912 * SECONDS_PER_DAY, &[]), date(1446, MUHARRAM, 1));
+    }
+
+    #[test]
+    fn tabular_months_are_29_or_30_days() {
+        for year in 1440..1460 {
+            for month in MUHARRAM..=DHU_AL_HIJJAH {
+                let (next_year, next) = next_month(year, month);
+                let length = tabular_month_start(next_year, next) - tabular_month_start(year, month);
+                assert!((29..=30).contains(&length), "{year}-{month} has {length} days");
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_every_day() {
+        let adjustments = early_shawwal();
+        for adjustments in [&[][..], &adjustments[..]] {
+            for day in (RAMADAN_1445 - 400)..(RAMADAN_1445 + 400) {
+                let hijri = from_unix_timestamp(day * SECONDS_PER_DAY + 43_200, adjustments);
+                assert!((1..=30).contains(&hijri.day));
+                assert_eq!(to_unix_timestamp(hijri, adjustments), day * SECONDS_PER_DAY);
+            }
+        }
+    }
+
+    #[test]
+    fn adjustment_moves_the_month_start() {
+        let shawwal = tabular_month_start(1445, SHAWWAL);
+        let adjustments = early_shawwal();
+        assert_eq!(
+            from_unix_timestamp((shawwal - 2) * SECONDS_PER_DAY, &adjustments),
+            date(1445, RAMADAN, 29)
+        );
+        assert_eq!(
+            from_unix_timestamp((shawwal - 1) * SECONDS_PER_DAY, &adjustments),
+            date(1445, SHAWWAL, 1)
+        );
+        assert_eq!(from_unix_timestamp(shawwal * SECONDS_PER_DAY, &adjustments), date(1445, SHAWWAL, 2));
+    }
+
+    #[test]
+    fn month_start_validation() {
+        let shawwal = tabular_month_start(1445, SHAWWAL);
+        // Tabular Ramadan 1445 has 30 days and Shawwal 29
+        assert!(is_valid_month_start(1445, SHAWWAL, shawwal, &[]));
+        assert!(is_valid_month_start(1445, SHAWWAL, shawwal - 1, &[]));
+        assert!(!is_valid_month_start(1445, SHAWWAL, shawwal + 1, &[]));
+        assert!(!is_valid_month_start(1445, SHAWWAL, shawwal - 2, &[]));
+        // Once Dhu al-Qadah is published a day early, Shawwal must start early too
+        let dhu_al_qadah = [MonthStart {
+            year: 1445,
+            month: 11,
+            unix_day: tabular_month_start(1445, 11) - 1,
+        }];
+        assert!(!is_valid_month_start(1445, SHAWWAL, shawwal, &dhu_al_qadah));
+        assert!(is_valid_month_start(1445, SHAWWAL, shawwal - 1, &dhu_al_qadah));
+    }
+
+    #[test]
+    fn period_totals_reset_with_the_month_and_year() {
+        let today = date(1445, RAMADAN, 15);
+        assert_eq!(period_totals(1445, RAMADAN, 10, 30, today), (10, 30));
+        assert_eq!(period_totals(1445, RAMADAN - 1, 10, 30, today), (0, 30));
+        assert_eq!(period_totals(1444, RAMADAN, 10, 30, today), (0, 0));
+    }
+
+    #[test]
+    fn add_years_keeps_the_hijri_date() {
+        let start = date(1445, RAMADAN, 15);
+        assert_eq!(add_years(start, 1), date(1446, RAMADAN, 15));
+    }
+}
@@ -0,0 +1,94 @@
+// Identity Module
+//
+// Mustahik records never hold a name or national id. They keep
+// sha256(salt || NIK), with the salt kept by the institution, and a pointer to
+// the institution's off-chain record.
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+use crate::ZakaChainError;
+
+pub const MAX_DATA_URI_LEN: usize = 128;
+
+pub fn identity_hash(nik: &str, salt: &[u8; 32]) -> [u8; 32] {
+    hashv(&[salt, nik.as_bytes()]).to_bytes()
+}
+
+// True if the revealed NIK and salt hash to the stored commitment
+pub fn verify_identity(stored: &[u8; 32], nik: &str, salt: &[u8; 32]) -> bool {
+    identity_hash(nik, salt) == *stored
+}
+
+pub fn check_data_uri(data_uri: &str) -> Result<()> {
+    require!(data_uri.len() <= MAX_DATA_URI_LEN, ZakaChainError::DataUriTooLong);
+    Ok(())
+}
+
+// The key a registered institution program signs registry calls with: its
+// ["state"] PDA, which only the program itself can sign for through CPI
+pub fn institution_authority(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"state"], program_id).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IdentityRecord;
+
+    fn empty_record() -> IdentityRecord {
+        IdentityRecord {
+            dedup_hash: [0; 32],
+            enrolment_count: 0,
+            total_received: 0,
+            first_enrolled_at: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn verifies_only_the_committed_nik_and_salt() {
+        let salt = [7; 32];
+        let stored = identity_hash("3201010101010001", &salt);
+        assert!(verify_identity(&stored, "3201010101010001", &salt));
+        assert!(!verify_identity(&stored, "3201010101010002", &salt));
+        assert!(!verify_identity(&stored, "3201010101010001", &[8; 32]));
+    }
+
+    #[test]
+    fn limits_the_data_uri_length() {
+        assert!(check_data_uri(&"a".repeat(MAX_DATA_URI_LEN)).is_ok());
+        assert!(check_data_uri(&"a".repeat(MAX_DATA_URI_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn institution_authority_is_the_program_state_pda() {
+        let program_id = Pubkey::new_unique();
+        let authority = institution_authority(&program_id);
+        assert_eq!(authority, Pubkey::find_program_address(&[b"state"], &program_id).0);
+        assert!(!authority.is_on_curve());
+        assert_ne!(authority, institution_authority(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn counts_enrolments_across_institutions() {
+        let mut record = empty_record();
+        assert_eq!(record.enrol([1; 32], 100, 254), 0);
+        assert_eq!(record.enrol([1; 32], 200, 253), 1);
+        assert_eq!(record.dedup_hash, [1; 32]);
+        assert_eq!(record.first_enrolled_at, 100);
+        assert_eq!(record.bump, 254);
+        assert_eq!(record.enrolment_count, 2);
+    }
+
+    #[test]
+    fn keeps_reported_totals_when_an_enrolment_is_withdrawn() {
+        let mut record = empty_record();
+        record.enrol([1; 32], 100, 254);
+        record.enrol([1; 32], 200, 254);
+        record.record_disbursement(50_000);
+        record.record_disbursement(25_000);
+        record.withdraw();
+        assert_eq!(record.enrolment_count, 1);
+        assert_eq!(record.total_received, 75_000);
+    }
+}
@@ -3,7 +3,12 @@ use std::str::FromStr;
 
 declare_id!("375fwyjaiv8qfFhhBd8kbHnQy5VVKxEBgKMCGDkbxUZm");
 
+pub mod hijri;
+pub mod identity;
+
 pub const PROGRAM_DEPLOYER: &str = "F2sKSFqHi4NXsez7dfafW3rCA9YTCK5aNMrCZsNdoq9j";
+pub const MAX_MONTH_STARTS: usize = 24;
+pub const MAX_MONTH_START_DRIFT: i64 = 2; // days from the tabular calendar
 
 // CPI Account Structs
 #[derive(Accounts)]
@@ -57,6 +62,14 @@ pub enum ZakaChainError {
     Unauthorized,
     #[msg("Invalid fee percentage")]
     InvalidFeePercentage,
+    #[msg("Invalid Hijri month start")]
+    InvalidMonthStart,
+    #[msg("Month start would make a month shorter than 29 or longer than 30 days")]
+    InvalidMonthLength,
+    #[msg("Data URI is too long (max 128 characters)")]
+    DataUriTooLong,
+    #[msg("Mustahik account is not owned by the institution's program")]
+    InvalidMustahikAccount,
 }
 
 #[program]
@@ -142,6 +155,157 @@ pub mod zakachain_core {
         
         Ok(())
     }
+
+    pub fn initialize_hijri_calendar(ctx: Context<InitializeHijriCalendar>) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            ctx.accounts.amil.key() == ctx.accounts.state.amil,
+            ZakaChainError::Unauthorized
+        );
+
+        ctx.accounts.hijri_calendar.month_starts = Vec::new();
+        Ok(())
+    }
+
+    // Publishes a rukyat-based month start, replacing any earlier entry for the same month.
+    // Only the latest MAX_MONTH_STARTS months are kept, so programs store the Hijri date of
+    // a record when they write it rather than converting its timestamp again later.
+    pub fn publish_month_start(
+        ctx: Context<PublishMonthStart>,
+        year: u16,
+        month: u8,
+        unix_day: i64,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.amil,
+            ZakaChainError::Unauthorized
+        );
+        require!(
+            year > 0 && (hijri::MUHARRAM..=hijri::DHU_AL_HIJJAH).contains(&month),
+            ZakaChainError::InvalidMonthStart
+        );
+        require!(
+            (unix_day - hijri::tabular_month_start(year, month)).abs() <= MAX_MONTH_START_DRIFT,
+            ZakaChainError::InvalidMonthStart
+        );
+
+        let month_starts = &mut ctx.accounts.hijri_calendar.month_starts;
+        month_starts.retain(|m| !(m.year == year && m.month == month));
+        // Both this month and the one before it must stay 29 or 30 days long
+        require!(
+            hijri::is_valid_month_start(year, month, unix_day, month_starts),
+            ZakaChainError::InvalidMonthLength
+        );
+        if month_starts.len() == MAX_MONTH_STARTS {
+            // Drop the earliest month, which need not be the first entry
+            // since corrections can be published out of order
+            let (earliest, oldest) = month_starts
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, m)| (m.year, m.month))
+                .map(|(i, m)| (i, (m.year, m.month)))
+                .unwrap();
+            require!((year, month) > oldest, ZakaChainError::InvalidMonthStart);
+            month_starts.remove(earliest);
+        }
+        month_starts.push(hijri::MonthStart { year, month, unix_day });
+
+        emit!(MonthStartPublished {
+            year,
+            month,
+            unix_day,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Admits a program to the identity registry. The institution acts through the
+    // program's ["state"] PDA, which only the program itself can sign for, so
+    // registry calls always come through its own instructions.
+    pub fn register_institution(ctx: Context<RegisterInstitution>, program_id: Pubkey) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.amil,
+            ZakaChainError::Unauthorized
+        );
+
+        let institution = &mut ctx.accounts.institution;
+        institution.program_id = program_id;
+        institution.authority = identity::institution_authority(&program_id);
+        institution.registered_at = Clock::get()?.unix_timestamp;
+        institution.bump = ctx.bumps.institution;
+
+        emit!(InstitutionRegistered {
+            institution: institution.key(),
+            program_id,
+            authority: institution.authority,
+            timestamp: institution.registered_at,
+        });
+        Ok(())
+    }
+
+    // Links an institution's mustahik record to a shared identity commitment.
+    // `dedup_hash` is HMAC-SHA256(NIK) under a key held by the consortium's hashing
+    // service, so every institution gets the same value for the same person
+    // without being able to reverse it. Returns the number of enrolments held by
+    // other institutions.
+    pub fn enrol_identity(ctx: Context<EnrolIdentity>, dedup_hash: [u8; 32]) -> Result<u32> {
+        let now = Clock::get()?.unix_timestamp;
+        let mustahik_account = ctx.accounts.mustahik_account.key();
+        let identity = &mut ctx.accounts.identity;
+        let existing_enrolments = identity.enrol(dedup_hash, now, ctx.bumps.identity);
+
+        let enrolment = &mut ctx.accounts.enrolment;
+        enrolment.dedup_hash = dedup_hash;
+        enrolment.institution = ctx.accounts.institution.key();
+        enrolment.mustahik_account = mustahik_account;
+        enrolment.total_received = 0;
+        enrolment.enrolled_at = now;
+        enrolment.bump = ctx.bumps.enrolment;
+
+        emit!(IdentityEnrolled {
+            dedup_hash,
+            institution: enrolment.institution,
+            mustahik_account,
+            existing_enrolments,
+            total_received: identity.total_received,
+            timestamp: now,
+        });
+        Ok(existing_enrolments)
+    }
+
+    // Adds a disbursement to the institution's enrolment and the cross-institution total
+    pub fn report_disbursement(ctx: Context<ReportDisbursement>, amount: u64) -> Result<()> {
+        let enrolment = &mut ctx.accounts.enrolment;
+        enrolment.total_received = enrolment.total_received.checked_add(amount).unwrap();
+        let identity = &mut ctx.accounts.identity;
+        identity.record_disbursement(amount);
+
+        emit!(DisbursementReported {
+            dedup_hash: identity.dedup_hash,
+            institution: enrolment.institution,
+            amount,
+            total_received: identity.total_received,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Removes the institution's link; reported totals stay on the identity record
+    // and the enrolment's rent goes to `receiver`
+    pub fn withdraw_enrolment(ctx: Context<WithdrawEnrolment>) -> Result<()> {
+        let identity = &mut ctx.accounts.identity;
+        identity.withdraw();
+
+        emit!(EnrolmentWithdrawn {
+            dedup_hash: identity.dedup_hash,
+            institution: ctx.accounts.institution.key(),
+            remaining_enrolments: identity.enrolment_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
@@ -173,6 +337,126 @@ pub struct UpdateFeePercentage<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct InitializeHijriCalendar<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + HijriCalendar::LEN,
+        seeds = [b"hijri_calendar"],
+        bump
+    )]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PublishMonthStart<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut, seeds = [b"hijri_calendar"], bump)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(program_id: Pubkey)]
+pub struct RegisterInstitution<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Institution::LEN,
+        seeds = [b"institution", program_id.as_ref()],
+        bump
+    )]
+    pub institution: Account<'info, Institution>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(dedup_hash: [u8; 32])]
+pub struct EnrolIdentity<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + IdentityRecord::LEN,
+        seeds = [b"identity", dedup_hash.as_ref()],
+        bump
+    )]
+    pub identity: Account<'info, IdentityRecord>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Enrolment::LEN,
+        seeds = [b"enrolment", dedup_hash.as_ref(), institution.key().as_ref()],
+        bump
+    )]
+    pub enrolment: Account<'info, Enrolment>,
+    #[account(
+        seeds = [b"institution", institution.program_id.as_ref()],
+        bump = institution.bump,
+        has_one = authority
+    )]
+    pub institution: Account<'info, Institution>,
+    /// The institution program's state PDA, signing through CPI
+    pub authority: Signer<'info>,
+    /// CHECK: only its owner is checked; the institution's program keeps the record
+    #[account(owner = institution.program_id @ ZakaChainError::InvalidMustahikAccount)]
+    pub mustahik_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReportDisbursement<'info> {
+    #[account(mut, seeds = [b"identity", identity.dedup_hash.as_ref()], bump = identity.bump)]
+    pub identity: Account<'info, IdentityRecord>,
+    #[account(
+        mut,
+        seeds = [b"enrolment", identity.dedup_hash.as_ref(), institution.key().as_ref()],
+        bump = enrolment.bump,
+        has_one = institution
+    )]
+    pub enrolment: Account<'info, Enrolment>,
+    #[account(
+        seeds = [b"institution", institution.program_id.as_ref()],
+        bump = institution.bump,
+        has_one = authority
+    )]
+    pub institution: Account<'info, Institution>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawEnrolment<'info> {
+    #[account(mut, seeds = [b"identity", identity.dedup_hash.as_ref()], bump = identity.bump)]
+    pub identity: Account<'info, IdentityRecord>,
+    #[account(
+        mut,
+        close = receiver,
+        seeds = [b"enrolment", identity.dedup_hash.as_ref(), institution.key().as_ref()],
+        bump = enrolment.bump,
+        has_one = institution
+    )]
+    pub enrolment: Account<'info, Enrolment>,
+    #[account(
+        seeds = [b"institution", institution.program_id.as_ref()],
+        bump = institution.bump,
+        has_one = authority
+    )]
+    pub institution: Account<'info, Institution>,
+    pub authority: Signer<'info>,
+    /// CHECK: only receives the enrolment's rent
+    #[account(mut)]
+    pub receiver: UncheckedAccount<'info>,
+}
+
 #[account]
 pub struct ZakaChainState {
     pub amil: Pubkey,
@@ -194,4 +478,118 @@ impl ZakaChainState {
         1 + // is_initialized
         8 + // last_withdrawal_timestamp
         4; // manual_withdrawal_count
-} 
\ No newline at end of file
+}
+
+#[account]
+pub struct HijriCalendar {
+    pub month_starts: Vec<hijri::MonthStart>,
+}
+
+impl HijriCalendar {
+    pub const LEN: usize = 4 + (hijri::MonthStart::LEN * MAX_MONTH_STARTS);
+}
+
+#[account]
+pub struct Institution {
+    pub program_id: Pubkey,
+    /// The program's ["state"] PDA
+    pub authority: Pubkey,
+    pub registered_at: i64,
+    pub bump: u8,
+}
+
+impl Institution {
+    pub const LEN: usize = 32 + 32 + 8 + 1;
+}
+
+#[account]
+pub struct IdentityRecord {
+    pub dedup_hash: [u8; 32],
+    pub enrolment_count: u32,
+    pub total_received: u64,
+    pub first_enrolled_at: i64,
+    pub bump: u8,
+}
+
+impl IdentityRecord {
+    pub const LEN: usize = 32 + 4 + 8 + 8 + 1;
+
+    /// Adds an institution's enrolment, initialising the record on the first
+    /// one. Returns the number of enrolments held before this one.
+    pub fn enrol(&mut self, dedup_hash: [u8; 32], now: i64, bump: u8) -> u32 {
+        if self.first_enrolled_at == 0 {
+            self.dedup_hash = dedup_hash;
+            self.first_enrolled_at = now;
+            self.bump = bump;
+        }
+        let existing_enrolments = self.enrolment_count;
+        self.enrolment_count = self.enrolment_count.checked_add(1).unwrap();
+        existing_enrolments
+    }
+
+    pub fn record_disbursement(&mut self, amount: u64) {
+        self.total_received = self.total_received.checked_add(amount).unwrap();
+    }
+
+    /// Removes an enrolment; reported totals stay.
+    pub fn withdraw(&mut self) {
+        self.enrolment_count = self.enrolment_count.checked_sub(1).unwrap();
+    }
+}
+
+#[account]
+pub struct Enrolment {
+    pub dedup_hash: [u8; 32],
+    pub institution: Pubkey,
+    pub mustahik_account: Pubkey,
+    pub total_received: u64,
+    pub enrolled_at: i64,
+    pub bump: u8,
+}
+
+impl Enrolment {
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 1;
+}
+
+#[event]
+pub struct MonthStartPublished {
+    pub year: u16,
+    pub month: u8,
+    pub unix_day: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InstitutionRegistered {
+    pub institution: Pubkey,
+    pub program_id: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct IdentityEnrolled {
+    pub dedup_hash: [u8; 32],
+    pub institution: Pubkey,
+    pub mustahik_account: Pubkey,
+    pub existing_enrolments: u32,
+    pub total_received: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisbursementReported {
+    pub dedup_hash: [u8; 32],
+    pub institution: Pubkey,
+    pub amount: u64,
+    pub total_received: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EnrolmentWithdrawn {
+    pub dedup_hash: [u8; 32],
+    pub institution: Pubkey,
+    pub remaining_enrolments: u32,
+    pub timestamp: i64,
+}
@@ -1,9 +1,8 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{Token, TokenAccount};
 use anchor_spl::associated_token::AssociatedToken;
 use zakachain_core::program::ZakachainCore;
 use zakachain_core::ZakaChainState;
-use zakachain_core::cpi::accounts::UpdateDistribution;
 use std::str::FromStr;
 
 declare_id!("Hpfy7Cyo1mV4fHgU2vVPJ57FvGSRNNcpMPrrV2a2ugZf");
@@ -12,40 +11,32 @@ pub const MAX_MUSTAHIKS: usize = 50;
 pub const MAX_DESCRIPTION_LEN: usize = 100;
 pub const PROGRAM_DEPLOYER: &str = "F2sKSFqHi4NXsez7dfafW3rCA9YTCK5aNMrCZsNdoq9j";
 
+/// Winds down the mustahik lists registered before applications moved to the
+/// main program's survey and approval workflow. The lists store plain names,
+/// so the amil removes entries or closes whole lists and re-onboards those
+/// mustahiks through the main program.
 #[program]
 pub mod zakachain_distribution {
     use super::*;
 
-    pub fn add_mustahik(
-        ctx: Context<AddMustahik>,
-        name: String,
-        address: Pubkey,
-    ) -> Result<()> {
-        let state = &ctx.accounts.state;
-        require!(state.is_initialized, ZakaChainError::NotInitialized);
-        require!(
-            ctx.accounts.authority.key() == state.amil || 
-            ctx.accounts.authority.key() == Pubkey::from_str(PROGRAM_DEPLOYER).unwrap(),
-            ZakaChainError::Unauthorized
-        );
-        require!(
-            ctx.accounts.mustahik_list.mustahiks.len() < MAX_MUSTAHIKS,
-            ZakaChainError::MaxMustahiksReached
-        );
-        let mustahik = Mustahik {
-            name,
-            address,
-            total_received: 0,
-        };
-        ctx.accounts.mustahik_list.mustahiks.push(mustahik);
-        Ok(())
+    /// Deprecated: mustahiks apply through the main program's
+    /// `submit_application`. Kept so existing clients get a clear error.
+    pub fn add_mustahik(_ctx: Context<AddMustahik>, _name: String, _address: Pubkey) -> Result<()> {
+        err!(ZakaChainError::Deprecated)
+    }
+
+    /// Deprecated: distributions go through the main program's
+    /// `distribute_to_mustahik`, which checks status, eligibility and caps.
+    /// Kept so existing clients get a clear error.
+    pub fn distribute_to_mustahik(_ctx: Context<DistributeToMustahik>, _amount: u64) -> Result<()> {
+        err!(ZakaChainError::Deprecated)
     }
 
     pub fn remove_mustahik(ctx: Context<RemoveMustahik>, index: usize) -> Result<()> {
         let state = &ctx.accounts.state;
         require!(state.is_initialized, ZakaChainError::NotInitialized);
         require!(
-            ctx.accounts.authority.key() == state.amil || 
+            ctx.accounts.authority.key() == state.amil ||
             ctx.accounts.authority.key() == Pubkey::from_str(PROGRAM_DEPLOYER).unwrap(),
             ZakaChainError::Unauthorized
         );
@@ -57,49 +48,26 @@ pub mod zakachain_distribution {
         Ok(())
     }
 
-    pub fn distribute_to_mustahik(
-        ctx: Context<DistributeToMustahik>,
-        amount: u64,
-    ) -> Result<()> {
+    /// Closes a mustahik list, erasing the names it holds, and returns its
+    /// rent to the authority. `LegacyMustahikClosed` reports each entry's
+    /// wallet and total received, without the name, for re-onboarding.
+    pub fn close_mustahik_list(ctx: Context<CloseMustahikList>) -> Result<()> {
         let state = &ctx.accounts.state;
         require!(state.is_initialized, ZakaChainError::NotInitialized);
         require!(
-            ctx.accounts.authority.key() == state.amil || 
+            ctx.accounts.authority.key() == state.amil ||
             ctx.accounts.authority.key() == Pubkey::from_str(PROGRAM_DEPLOYER).unwrap(),
             ZakaChainError::Unauthorized
         );
-        let mustahik_list = &ctx.accounts.mustahik_list;
-        require!(
-            mustahik_list.mustahiks.iter().any(|m| m.address == ctx.accounts.mustahik.key()),
-            ZakaChainError::InvalidMustahik
-        );
-        // Transfer tokens to mustahik
-        let seeds: &[&[u8]] = &[b"state"];
-        let signer = &[seeds];
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.program_token_account.to_account_info(),
-                to: ctx.accounts.mustahik_token_account.to_account_info(),
-                authority: ctx.accounts.state.to_account_info(),
-            },
-            signer,
-        );
-        token::transfer(transfer_ctx, amount)?;
-        // Update state through CPI
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.core_program.to_account_info(),
-            UpdateDistribution {
-                state: ctx.accounts.state.to_account_info(),
-                authority: ctx.accounts.authority.to_account_info(),
-            },
-        );
-        zakachain_core::cpi::update_distribution(cpi_ctx, amount)?;
-        emit!(ZakatDistributed {
-            mustahik: ctx.accounts.mustahik.key(),
-            amount,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+        let timestamp = Clock::get()?.unix_timestamp;
+        for mustahik in ctx.accounts.mustahik_list.mustahiks.iter() {
+            emit!(LegacyMustahikClosed {
+                mustahik_list: ctx.accounts.mustahik_list.key(),
+                mustahik: mustahik.address,
+                total_received: mustahik.total_received,
+                timestamp,
+            });
+        }
         Ok(())
     }
 }
@@ -138,6 +106,15 @@ pub struct DistributeToMustahik<'info> {
     pub core_program: Program<'info, ZakachainCore>,
 }
 
+#[derive(Accounts)]
+pub struct CloseMustahikList<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut, close = authority)]
+    pub mustahik_list: Account<'info, MustahikList>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
 #[account]
 pub struct MustahikList {
     pub mustahiks: Vec<Mustahik>,
@@ -151,9 +128,10 @@ pub struct Mustahik {
 }
 
 #[event]
-pub struct ZakatDistributed {
+pub struct LegacyMustahikClosed {
+    pub mustahik_list: Pubkey,
     pub mustahik: Pubkey,
-    pub amount: u64,
+    pub total_received: u64,
     pub timestamp: i64,
 }
 
@@ -169,4 +147,6 @@ pub enum ZakaChainError {
     InvalidIndex,
     #[msg("Invalid mustahik address")]
     InvalidMustahik,
-} 
\ No newline at end of file
+    #[msg("Deprecated: use the main program's application workflow and distributions")]
+    Deprecated,
+}
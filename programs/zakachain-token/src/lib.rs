@@ -12,6 +12,7 @@ pub mod zakachain_token {
     pub fn receive_zakat(
         ctx: Context<ReceiveZakat>,
         amount: u64,
+        payment_id: [u8; 16],
     ) -> Result<()> {
         let state = &ctx.accounts.state;
         require!(state.is_initialized, ZakaChainError::NotInitialized);
@@ -59,11 +60,22 @@ pub mod zakachain_token {
         );
         zakachain_core::cpi::update_collection(cpi_ctx, net_amount, amil_fee)?;
 
+        // Record the payment id so client retries cannot charge twice
+        let timestamp = Clock::get()?.unix_timestamp;
+        let payment = &mut ctx.accounts.payment;
+        payment.payer = ctx.accounts.payer.key();
+        payment.payment_id = payment_id;
+        payment.amount = net_amount;
+        payment.amil_fee = amil_fee;
+        payment.timestamp = timestamp;
+        payment.bump = ctx.bumps.payment;
+
         emit!(ZakatReceived {
             payer: ctx.accounts.payer.key(),
+            payment_id,
             amount: net_amount,
             amil_fee,
-            timestamp: Clock::get()?.unix_timestamp,
+            timestamp,
         });
 
         Ok(())
@@ -147,6 +159,7 @@ pub mod zakachain_token {
 }
 
 #[derive(Accounts)]
+#[instruction(amount: u64, payment_id: [u8; 16])]
 pub struct ReceiveZakat<'info> {
     pub state: Account<'info, zakachain_core::ZakaChainState>,
     #[account(mut)]
@@ -157,8 +170,17 @@ pub struct ReceiveZakat<'info> {
     pub program_token_account: Account<'info, TokenAccount>,
     #[account(mut)]
     pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", payer.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
     pub token_program: Program<'info, Token>,
     pub core_program: Program<'info, ZakachainCore>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -181,9 +203,24 @@ pub struct WithdrawForManualDistribution<'info> {
     pub core_program: Program<'info, ZakachainCore>,
 }
 
+#[account]
+pub struct PaymentMarker {
+    pub payer: Pubkey,
+    pub payment_id: [u8; 16],
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+    pub bump: u8,
+}
+
+impl PaymentMarker {
+    pub const LEN: usize = 32 + 16 + 8 + 8 + 8 + 1;
+}
+
 #[event]
 pub struct ZakatReceived {
     pub payer: Pubkey,
+    pub payment_id: [u8; 16],
     pub amount: u64,
     pub amil_fee: u64,
     pub timestamp: i64,
@@ -1,12 +1,43 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::sysvar::instructions as instructions_sysvar;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
 use anchor_spl::associated_token::AssociatedToken;
 use std::str::FromStr;
+use zakachain_core::hijri::{self, HijriDate, MonthStart};
+use zakachain_core::identity::{self, MAX_DATA_URI_LEN};
+use zakachain_core::program::ZakachainCore;
+use zakachain_core::HijriCalendar;
 
 declare_id!("3EJSTPJYM3BaNBvL7haWnhXoNh5GvmsQfwL1QQ2am3GJ");
 
-pub const MAX_MUSTAHIKS: usize = 50;
 pub const MAX_DESCRIPTION_LEN: usize = 100;
+pub const DEFAULT_REFUND_WINDOW: i64 = 7 * 86400; // 7 days
+pub const MAX_REFUND_WINDOW: i64 = 30 * 86400; // 30 days
+pub const MAX_FUND_NAME_LEN: usize = 32;
+// Each part adds its fund and receipt to the transaction; six parts keep it
+// within the 1232-byte packet limit
+pub const MAX_SPLIT_PARTS: usize = 6;
+pub const MAX_INVOICE_REFERENCE_LEN: usize = 64;
+pub const MAX_MATCH_RATIO_BPS: u16 = 50_000; // 5:1
+pub const MAX_CAMPAIGN_TITLE_LEN: usize = 64;
+pub const MAX_BENEFICIARIES: usize = 5;
+pub const MAX_AGENT_NAME_LEN: usize = 32;
+// Each entry adds two accounts; eight keeps a payroll with its fixed accounts
+// inside the 1232-byte transaction limit.
+pub const MAX_PAYROLL_ENTRIES: usize = 8;
+pub const MAX_INTEGRATION_NAME_LEN: usize = 32;
+pub const ZAKAT_RATE_BPS: u64 = 250; // 2.5%
+pub const MAX_PRICE_AGE: i64 = 24 * 60 * 60; // 1 day
+pub const MAX_PRICE_DECIMALS: u8 = 38; // 10^38 is the largest power of ten in a u128
+pub const ELIGIBILITY_PERIOD_YEARS: u16 = 1; // Hijri years
+pub const ASNAF_COUNT: usize = 8;
+pub const MAX_ROUND_LEAVES: u32 = 65_536;
+pub const MAX_BATCH_DISTRIBUTIONS: usize = 10;
+pub const ELIGIBILITY_NOTICE_PERIOD: i64 = 30 * 86400; // 30 days
+pub const LEGACY_MAX_MUSTAHIKS: usize = 50; // the original release's list size
 pub const PROGRAM_DEPLOYER: &str = "F2sKSFqHi4NXsez7dfafW3rCA9YTCK5aNMrCZsNdoq9j";
 
 #[program]
@@ -31,7 +62,166 @@ pub mod zakachain {
         state.is_initialized = true;
         state.last_withdrawal_timestamp = 0;
         state.manual_withdrawal_count = 0;
-        state.mustahiks = Vec::new();
+        state.mustahik_count = 0;
+        state.refund_window = DEFAULT_REFUND_WINDOW;
+        state.receipt_count = 0;
+        state.invoice_count = 0;
+        state.period_year = 0;
+        state.period_month = 0;
+        state.period_zakat_collected = 0;
+        state.period_amil_fees_collected = 0;
+        state.period_zakat_distributed = 0;
+        state.year_zakat_collected = 0;
+        state.year_amil_fees_collected = 0;
+        state.year_zakat_distributed = 0;
+        state.max_relayer_fee = 0;
+        state.campaign_count = 0;
+        state.nisab = 0;
+        state.asnaf_caps = [DisbursementCaps::default(); ASNAF_COUNT];
+        state.round_count = 0;
+        Ok(())
+    }
+
+    /// One-off upgrade of a state account created by the original release,
+    /// which kept mustahiks in a `Vec<Pubkey>` inside the state. Rewrites the
+    /// account in the current layout and resizes it. Legacy mustahiks have no
+    /// `Mustahik` account yet, so the list moves to the `legacy_mustahiks`
+    /// PDA until `migrate_legacy_mustahik` creates one for each of them. Run
+    /// it right after the upgrade: no other instruction can read the legacy
+    /// layout.
+    pub fn migrate_state(ctx: Context<MigrateState>) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        let legacy = {
+            let data = state_info.try_borrow_data()?;
+            require!(
+                state_info.owner == &crate::ID
+                    && data.len() == 8 + LegacyZakaChainState::LEN
+                    && data[..8] == ZakaChainState::DISCRIMINATOR,
+                ZakaChainError::StateAlreadyMigrated
+            );
+            LegacyZakaChainState::deserialize(&mut &data[8..])?
+        };
+        require!(ctx.accounts.amil.key() == legacy.amil, ZakaChainError::Unauthorized);
+        let state = ZakaChainState {
+            amil: legacy.amil,
+            amil_fee_percentage: legacy.amil_fee_percentage,
+            total_zakat_collected: legacy.total_zakat_collected,
+            total_zakat_distributed: legacy.total_zakat_distributed,
+            total_amil_fees_collected: legacy.total_amil_fees_collected,
+            is_initialized: legacy.is_initialized,
+            last_withdrawal_timestamp: legacy.last_withdrawal_timestamp,
+            manual_withdrawal_count: legacy.manual_withdrawal_count,
+            mustahik_count: 0,
+            refund_window: DEFAULT_REFUND_WINDOW,
+            receipt_count: 0,
+            invoice_count: 0,
+            period_year: 0,
+            period_month: 0,
+            period_zakat_collected: 0,
+            period_amil_fees_collected: 0,
+            period_zakat_distributed: 0,
+            year_zakat_collected: 0,
+            year_amil_fees_collected: 0,
+            year_zakat_distributed: 0,
+            max_relayer_fee: 0,
+            campaign_count: 0,
+            nisab: 0,
+            asnaf_caps: [DisbursementCaps::default(); ASNAF_COUNT],
+            round_count: 0,
+        };
+        let space = 8 + ZakaChainState::LEN;
+        let rent = Rent::get()?.minimum_balance(space);
+        let lamports = state_info.lamports();
+        if lamports < rent {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.amil.to_account_info(),
+                        to: state_info.clone(),
+                    },
+                ),
+                rent - lamports,
+            )?;
+        }
+        state_info.realloc(space, false)?;
+        if lamports > rent {
+            state_info.sub_lamports(lamports - rent)?;
+            ctx.accounts.amil.add_lamports(lamports - rent)?;
+        }
+        state.try_serialize(&mut &mut state_info.try_borrow_mut_data()?[..])?;
+        let legacy_mustahiks = &mut ctx.accounts.legacy_mustahiks;
+        legacy_mustahiks.wallets = legacy.mustahiks.clone();
+        legacy_mustahiks.bump = ctx.bumps.legacy_mustahiks;
+        emit!(StateMigrated {
+            legacy_mustahiks: legacy.mustahiks,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Creates the `Mustahik` account of a mustahik registered in the original
+    /// release's state and takes them off the legacy list. They were eligible
+    /// before the upgrade, so the record starts active for one eligibility
+    /// period, without an identity hash until they apply again after a
+    /// negative re-survey. A wallet that already applied through
+    /// `submit_application` keeps its record and is only taken off the list.
+    pub fn migrate_legacy_mustahik(
+        ctx: Context<MigrateLegacyMustahik>,
+        wallet: Pubkey,
+        asnaf: Asnaf,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        let legacy_mustahiks = &mut ctx.accounts.legacy_mustahiks;
+        let index = legacy_mustahiks
+            .wallets
+            .iter()
+            .position(|legacy| *legacy == wallet)
+            .ok_or(ZakaChainError::InvalidMustahik)?;
+        legacy_mustahiks.wallets.remove(index);
+        let mustahik = &mut ctx.accounts.mustahik;
+        if mustahik.institution != Pubkey::default() {
+            return Ok(());
+        }
+        let timestamp = Clock::get()?.unix_timestamp;
+        mustahik.set_inner(Mustahik {
+            institution: state.key(),
+            wallet,
+            asnaf,
+            status: MustahikStatus::Active,
+            applicant: Pubkey::default(),
+            applied_at: 0,
+            surveyor: Pubkey::default(),
+            surveyed_at: 0,
+            assessment_hash: [0; 32],
+            survey_recommended: false,
+            approver: state.amil,
+            approved_at: timestamp,
+            eligible_until: eligibility_end(timestamp, &ctx.accounts.hijri_calendar.month_starts),
+            last_expiry_notice: 0,
+            total_received: 0,
+            payment_count: 0,
+            last_payment_at: 0,
+            period_year: 0,
+            period_month: 0,
+            month_received: 0,
+            year_received: 0,
+            caps: DisbursementCaps::default(),
+            identity_hash: [0; 32],
+            data_uri: String::new(),
+            dedup_hash: [0; 32],
+            enrolment: Pubkey::default(),
+            bump: ctx.bumps.mustahik,
+        });
+        state.mustahik_count = state.mustahik_count.checked_add(1).unwrap();
+        emit!(LegacyMustahikMigrated {
+            mustahik: wallet,
+            asnaf,
+            eligible_until: mustahik.eligible_until,
+            timestamp,
+        });
         Ok(())
     }
 
@@ -50,256 +240,5091 @@ pub mod zakachain {
         Ok(())
     }
 
-    pub fn add_mustahik(
-        ctx: Context<AddMustahik>,
-        mustahik_address: Pubkey,
-    ) -> Result<()> {
-        let state = &mut ctx.accounts.state;
-        require!(state.is_initialized, ZakaChainError::NotInitialized);
-        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
-        require!(state.mustahiks.len() < MAX_MUSTAHIKS, ZakaChainError::MaxMustahiksReached);
-        state.mustahiks.push(mustahik_address);
-        emit!(MustahikAdded {
-            mustahik: mustahik_address,
+    pub fn update_refund_window(ctx: Context<UpdateRefundWindow>, new_refund_window: i64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.authority.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(
+            (0..=MAX_REFUND_WINDOW).contains(&new_refund_window),
+            ZakaChainError::InvalidRefundWindow
+        );
+        ctx.accounts.state.refund_window = new_refund_window;
+        Ok(())
+    }
+
+    pub fn update_max_relayer_fee(ctx: Context<UpdateMaxRelayerFee>, new_max_relayer_fee: u64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.authority.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        ctx.accounts.state.max_relayer_fee = new_max_relayer_fee;
+        Ok(())
+    }
+
+    /// Sets the nisab threshold, in base units of the collection token.
+    pub fn update_nisab(ctx: Context<UpdateNisab>, new_nisab: u64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.authority.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(new_nisab > 0, ZakaChainError::InvalidAmount);
+        ctx.accounts.state.nisab = new_nisab;
+        emit!(NisabUpdated {
+            nisab: new_nisab,
             timestamp: Clock::get()?.unix_timestamp,
         });
         Ok(())
     }
 
-    pub fn remove_mustahik(ctx: Context<RemoveMustahik>, mustahik_address: Pubkey) -> Result<()> {
-        let state = &mut ctx.accounts.state;
+    /// Publishes the price of one whole token of `mint`, in base units of the
+    /// collection token.
+    pub fn update_price(ctx: Context<UpdatePrice>, price: u64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(
+            ctx.accounts.mint.decimals <= MAX_PRICE_DECIMALS,
+            ZakaChainError::InvalidPriceFeed
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.mint = ctx.accounts.mint.key();
+        price_feed.decimals = ctx.accounts.mint.decimals;
+        price_feed.price = price;
+        price_feed.updated_at = timestamp;
+        price_feed.bump = ctx.bumps.price_feed;
+        emit!(PriceUpdated {
+            mint: price_feed.mint,
+            price,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Returns the zakat due on `owner`'s token holdings via return data, for
+    /// wallets to prefill `collect_zakat`. `remaining_accounts` holds pairs of
+    /// the owner's token account and the price feed for its mint. Holdings
+    /// below nisab owe nothing.
+    pub fn calculate_zakat<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CalculateZakat<'info>>,
+        owner: Pubkey,
+    ) -> Result<u64> {
+        let state = &ctx.accounts.state;
         require!(state.is_initialized, ZakaChainError::NotInitialized);
-        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
-        if let Some(pos) = state.mustahiks.iter().position(|x| *x == mustahik_address) {
-            state.mustahiks.remove(pos);
-            emit!(MustahikRemoved {
-                mustahik: mustahik_address,
-                timestamp: Clock::get()?.unix_timestamp,
-            });
-            Ok(())
-        } else {
-            err!(ZakaChainError::InvalidMustahik)
+        require!(state.nisab > 0, ZakaChainError::NisabNotSet);
+        let holdings = ctx.remaining_accounts.chunks_exact(2);
+        require!(
+            holdings.len() > 0 && holdings.remainder().is_empty(),
+            ZakaChainError::InvalidHoldings
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        let mut seen: Vec<Pubkey> = Vec::new();
+        let mut total_value: u128 = 0;
+        for entry in holdings {
+            let token_account = Account::<TokenAccount>::try_from(&entry[0])?;
+            let price_feed = Account::<PriceFeed>::try_from(&entry[1])?;
+            require!(token_account.owner == owner, ZakaChainError::InvalidTokenAccount);
+            require!(!seen.contains(&entry[0].key()), ZakaChainError::InvalidHoldings);
+            seen.push(entry[0].key());
+            require!(price_feed.mint == token_account.mint, ZakaChainError::InvalidPriceFeed);
+            require!(
+                timestamp.checked_sub(price_feed.updated_at).unwrap() <= MAX_PRICE_AGE,
+                ZakaChainError::StalePrice
+            );
+            let scale = 10u128
+                .checked_pow(price_feed.decimals as u32)
+                .ok_or(ZakaChainError::InvalidPriceFeed)?;
+            let value = (token_account.amount as u128)
+                .checked_mul(price_feed.price as u128)
+                .unwrap()
+                .checked_div(scale)
+                .unwrap();
+            total_value = total_value.checked_add(value).unwrap();
+        }
+        if total_value < state.nisab as u128 {
+            return Ok(0);
         }
+        let zakat_due = total_value
+            .checked_mul(ZAKAT_RATE_BPS as u128)
+            .unwrap()
+            .checked_div(10_000)
+            .unwrap();
+        Ok(u64::try_from(zakat_due).unwrap_or(u64::MAX))
     }
 
-    pub fn collect_zakat(
-        ctx: Context<CollectZakat>,
-        amount: u64,
+    pub fn register_agent(
+        ctx: Context<RegisterAgent>,
+        wallet: Pubkey,
+        name: String,
+        commission_bps: u16,
     ) -> Result<()> {
-        let state = &mut ctx.accounts.state;
-        require!(state.is_initialized, ZakaChainError::NotInitialized);
-        let amil_fee = (amount as u128)
-            .checked_mul(state.amil_fee_percentage as u128)
-            .unwrap()
-            .checked_div(1000)
-            .unwrap() as u64;
-        let net_amount = amount.checked_sub(amil_fee).unwrap();
-        let transfer_accounts = Transfer {
-            from: ctx.accounts.payer_token_account.to_account_info(),
-            to: ctx.accounts.program_token_account.to_account_info(),
-            authority: ctx.accounts.payer.to_account_info(),
-        };
-        token::transfer(
-            CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
-            net_amount,
-        )?;
-        if amil_fee > 0 {
-            let transfer_accounts = Transfer {
-                from: ctx.accounts.payer_token_account.to_account_info(),
-                to: ctx.accounts.amil_token_account.to_account_info(),
-                authority: ctx.accounts.payer.to_account_info(),
-            };
-            token::transfer(
-                CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
-                amil_fee,
-            )?;
-        }
-        state.total_zakat_collected = state.total_zakat_collected.checked_add(net_amount).unwrap();
-        state.total_amil_fees_collected = state.total_amil_fees_collected.checked_add(amil_fee).unwrap();
-        emit!(ZakatCollected {
-            payer: ctx.accounts.payer.key(),
-            amount: net_amount,
-            amil_fee,
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(name.len() <= MAX_AGENT_NAME_LEN, ZakaChainError::AgentNameTooLong);
+        require!(commission_bps <= 10_000, ZakaChainError::InvalidCommission);
+        let agent = &mut ctx.accounts.agent;
+        agent.wallet = wallet;
+        agent.name = name;
+        agent.commission_bps = commission_bps;
+        agent.total_collected = 0;
+        agent.total_amil_fees = 0;
+        agent.commission_accrued = 0;
+        agent.commission_paid = 0;
+        agent.is_active = true;
+        agent.bump = ctx.bumps.agent;
+        emit!(AgentRegistered {
+            agent: agent.key(),
+            wallet,
+            commission_bps,
             timestamp: Clock::get()?.unix_timestamp,
         });
         Ok(())
     }
 
-    pub fn distribute_to_mustahik(
-        ctx: Context<DistributeToMustahik>,
-        amount: u64,
-    ) -> Result<()> {
-        let state_info = ctx.accounts.state.to_account_info();
-        let state = &mut ctx.accounts.state;
-        require!(state.is_initialized, ZakaChainError::NotInitialized);
-        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
-        require!(state.mustahiks.contains(&ctx.accounts.mustahik.key()), ZakaChainError::InvalidMustahik);
-        let seeds: &[&[u8]] = &[b"state"];
-        let signer = &[seeds];
+    pub fn update_agent(ctx: Context<UpdateAgent>, commission_bps: u16, is_active: bool) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(commission_bps <= 10_000, ZakaChainError::InvalidCommission);
+        let agent = &mut ctx.accounts.agent;
+        agent.commission_bps = commission_bps;
+        agent.is_active = is_active;
+        emit!(AgentUpdated {
+            agent: agent.key(),
+            commission_bps,
+            is_active,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pays out accrued commission from the amil's fee account to the agent.
+    pub fn pay_agent_commission(ctx: Context<PayAgentCommission>, amount: u64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let agent = &mut ctx.accounts.agent;
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        require!(
+            amount <= agent.commission_accrued.saturating_sub(agent.commission_paid),
+            ZakaChainError::InsufficientFunds
+        );
         let transfer_accounts = Transfer {
-            from: ctx.accounts.program_token_account.to_account_info(),
-            to: ctx.accounts.mustahik_token_account.to_account_info(),
-            authority: state_info,
+            from: ctx.accounts.amil_token_account.to_account_info(),
+            to: ctx.accounts.agent_token_account.to_account_info(),
+            authority: ctx.accounts.amil.to_account_info(),
         };
         token::transfer(
-            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
             amount,
         )?;
-        state.total_zakat_distributed = state.total_zakat_distributed.checked_add(amount).unwrap();
-        emit!(ZakatDistributed {
-            mustahik: ctx.accounts.mustahik.key(),
+        agent.commission_paid = agent.commission_paid.checked_add(amount).unwrap();
+        emit!(AgentCommissionPaid {
+            agent: agent.key(),
             amount,
             timestamp: Clock::get()?.unix_timestamp,
         });
         Ok(())
     }
 
-    pub fn withdraw_amil_fees(
-        ctx: Context<WithdrawAmilFees>,
-        amount: u64,
-    ) -> Result<()> {
-        let state_info = ctx.accounts.state.to_account_info();
-        let state = &mut ctx.accounts.state;
-        require!(state.is_initialized, ZakaChainError::NotInitialized);
-        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
-        require!(amount <= state.total_amil_fees_collected, ZakaChainError::InsufficientFunds);
-        let seeds: &[&[u8]] = &[b"state"];
-        let signer = &[seeds];
+    /// Returns commission that was paid out on payments refunded since. Until
+    /// it is repaid, the agent's new commission goes towards it first.
+    pub fn repay_agent_commission(ctx: Context<RepayAgentCommission>, amount: u64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        let agent = &mut ctx.accounts.agent;
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        require!(
+            amount <= agent.commission_paid.saturating_sub(agent.commission_accrued),
+            ZakaChainError::InvalidAmount
+        );
         let transfer_accounts = Transfer {
-            from: ctx.accounts.program_token_account.to_account_info(),
+            from: ctx.accounts.agent_token_account.to_account_info(),
             to: ctx.accounts.amil_token_account.to_account_info(),
-            authority: state_info,
+            authority: ctx.accounts.wallet.to_account_info(),
         };
         token::transfer(
-            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
             amount,
         )?;
-        state.total_amil_fees_collected = state.total_amil_fees_collected.checked_sub(amount).unwrap();
-        state.last_withdrawal_timestamp = Clock::get()?.unix_timestamp;
-        state.manual_withdrawal_count = state.manual_withdrawal_count.checked_add(1).unwrap();
-        emit!(AmilFeesWithdrawn {
-            amil: ctx.accounts.amil.key(),
+        agent.commission_paid = agent.commission_paid.checked_sub(amount).unwrap();
+        emit!(AgentCommissionRepaid {
+            agent: agent.key(),
             amount,
             timestamp: Clock::get()?.unix_timestamp,
         });
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = amil,
-        space = 8 + ZakaChainState::LEN,
-        seeds = [b"state"],
-        bump
-    )]
-    pub state: Account<'info, ZakaChainState>,
-    #[account(mut)]
-    pub amil: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    pub fn create_fund(
+        ctx: Context<CreateFund>,
+        fund_id: u8,
+        name: String,
+        amil_fee_percentage: u8,
+        window: CollectionWindow,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(amil_fee_percentage <= 125, ZakaChainError::InvalidFeePercentage);
+        require!(name.len() <= MAX_FUND_NAME_LEN, ZakaChainError::FundNameTooLong);
+        let fund = &mut ctx.accounts.fund;
+        fund.fund_id = fund_id;
+        fund.name = name;
+        fund.amil_fee_percentage = amil_fee_percentage;
+        fund.window = window;
+        fund.mint = ctx.accounts.mint.key();
+        fund.total_collected = 0;
+        fund.total_amil_fees_collected = 0;
+        fund.bump = ctx.bumps.fund;
+        emit!(FundCreated {
+            fund: fund.key(),
+            fund_id,
+            amil_fee_percentage,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct UpdateAuthority<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    pub authority: Signer<'info>,
-}
+    pub fn update_fund_fee_percentage(ctx: Context<UpdateFund>, new_fee_percentage: u8) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(new_fee_percentage <= 125, ZakaChainError::InvalidFeePercentage);
+        ctx.accounts.fund.amil_fee_percentage = new_fee_percentage;
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct UpdateFeePercentage<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    pub authority: Signer<'info>,
-}
+    pub fn register_officer(ctx: Context<RegisterOfficer>, wallet: Pubkey, role: OfficerRole) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let officer = &mut ctx.accounts.officer;
+        officer.wallet = wallet;
+        officer.role = role;
+        officer.bump = ctx.bumps.officer;
+        emit!(OfficerRegistered {
+            officer: wallet,
+            role,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct AddMustahik<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    pub amil: Signer<'info>,
-}
+    pub fn remove_officer(ctx: Context<RemoveOfficer>) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct RemoveMustahik<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    pub amil: Signer<'info>,
-}
+    /// Opens a mustahik application, submitted by the candidate themselves or
+    /// by a field officer on their behalf. The identity stays off chain:
+    /// `identity_hash` is sha256(salt || NIK) and `data_uri` points to the
+    /// institution's record. A rejected candidate may apply again with the
+    /// same `identity_hash`; their distribution history and caps carry over.
+    pub fn submit_application(
+        ctx: Context<SubmitApplication>,
+        mustahik_address: Pubkey,
+        asnaf: Asnaf,
+        identity_hash: [u8; 32],
+        data_uri: String,
+    ) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        identity::check_data_uri(&data_uri)?;
+        let applicant = ctx.accounts.applicant.key();
+        if applicant != mustahik_address {
+            let officer = ctx.accounts.officer.as_ref().ok_or(ZakaChainError::Unauthorized)?;
+            require!(
+                officer.wallet == applicant && officer.role == OfficerRole::FieldOfficer,
+                ZakaChainError::Unauthorized
+            );
+        }
+        let timestamp = Clock::get()?.unix_timestamp;
+        let mustahik = &mut ctx.accounts.mustahik;
+        if mustahik.institution == Pubkey::default() {
+            mustahik.institution = state.key();
+            mustahik.wallet = mustahik_address;
+            mustahik.total_received = 0;
+            mustahik.payment_count = 0;
+            mustahik.last_payment_at = 0;
+            mustahik.period_year = 0;
+            mustahik.period_month = 0;
+            mustahik.month_received = 0;
+            mustahik.year_received = 0;
+            mustahik.caps = DisbursementCaps::default();
+            mustahik.dedup_hash = [0; 32];
+            mustahik.enrolment = Pubkey::default();
+            mustahik.identity_hash = identity_hash;
+            mustahik.bump = ctx.bumps.mustahik;
+        } else {
+            require!(mustahik.status == MustahikStatus::Rejected, ZakaChainError::InvalidMustahikStatus);
+            // Migrated legacy mustahiks have no identity hash until they apply
+            if mustahik.identity_hash == [0; 32] {
+                mustahik.identity_hash = identity_hash;
+            }
+            require!(identity_hash == mustahik.identity_hash, ZakaChainError::IdentityMismatch);
+        }
+        mustahik.asnaf = asnaf;
+        mustahik.status = MustahikStatus::Applied;
+        mustahik.applicant = applicant;
+        mustahik.applied_at = timestamp;
+        mustahik.surveyor = Pubkey::default();
+        mustahik.surveyed_at = 0;
+        mustahik.assessment_hash = [0; 32];
+        mustahik.survey_recommended = false;
+        mustahik.approver = Pubkey::default();
+        mustahik.approved_at = 0;
+        mustahik.eligible_until = 0;
+        mustahik.last_expiry_notice = 0;
+        mustahik.data_uri = data_uri;
+        emit!(MustahikApplied {
+            mustahik: mustahik_address,
+            applicant,
+            asnaf,
+            timestamp,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct CollectZakat<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    #[account(mut)]
-    pub payer_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub program_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub amil_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-}
+    /// Records a surveyor's field assessment. The report itself stays off-chain;
+    /// `assessment_hash` commits to it. The surveyor hashes the NIK they
+    /// checked in the field with the application's salt, and it must match the
+    /// `identity_hash` the application was submitted with. The surveyor also
+    /// attests `dedup_hash`, the consortium hashing service's keyed hash of
+    /// the same NIK, used to enrol the mustahik in the core identity registry.
+    /// Pass zeros if the institution does not take part in the registry.
+    pub fn record_survey(
+        ctx: Context<RecordSurvey>,
+        identity_hash: [u8; 32],
+        dedup_hash: [u8; 32],
+        assessment_hash: [u8; 32],
+        recommended: bool,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.officer.role == OfficerRole::Surveyor, ZakaChainError::Unauthorized);
+        let mustahik = &mut ctx.accounts.mustahik;
+        require!(mustahik.status == MustahikStatus::Applied, ZakaChainError::InvalidMustahikStatus);
+        require!(identity_hash == mustahik.identity_hash, ZakaChainError::IdentityMismatch);
+        // An enrolled mustahik who re-applies stays linked to the same identity
+        require!(
+            mustahik.enrolment == Pubkey::default() || dedup_hash == mustahik.dedup_hash,
+            ZakaChainError::IdentityMismatch
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        mustahik.dedup_hash = dedup_hash;
+        mustahik.status = MustahikStatus::Surveyed;
+        mustahik.surveyor = ctx.accounts.surveyor.key();
+        mustahik.surveyed_at = timestamp;
+        mustahik.assessment_hash = assessment_hash;
+        mustahik.survey_recommended = recommended;
+        emit!(MustahikSurveyed {
+            mustahik: mustahik.wallet,
+            surveyor: mustahik.surveyor,
+            assessment_hash,
+            recommended,
+            timestamp,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct DistributeToMustahik<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    pub amil: Signer<'info>,
-    /// CHECK: This is the mustahik's wallet address
-    pub mustahik: AccountInfo<'info>,
-    #[account(mut)]
-    pub program_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub mustahik_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-}
+    /// Activates or rejects a surveyed application. The approver must not be
+    /// the surveyor. Only approved mustahiks count towards `mustahik_count`.
+    /// Passing the registry accounts enrols an approved mustahik in the core
+    /// identity registry under their attested `dedup_hash`, with the approver
+    /// paying rent; the registry's `IdentityEnrolled` event reports enrolments
+    /// held by other institutions.
+    pub fn review_application(ctx: Context<ReviewApplication>, approved: bool) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.officer.role == OfficerRole::Approver, ZakaChainError::Unauthorized);
+        require!(
+            ctx.accounts.mustahik.status == MustahikStatus::Surveyed,
+            ZakaChainError::InvalidMustahikStatus
+        );
+        require!(
+            ctx.accounts.approver.key() != ctx.accounts.mustahik.surveyor,
+            ZakaChainError::Unauthorized
+        );
+        if approved && ctx.accounts.mustahik.enrolment == Pubkey::default() {
+            if let (Some(core_program), Some(identity), Some(enrolment), Some(institution), Some(system_program)) = (
+                ctx.accounts.core_program.as_ref(),
+                ctx.accounts.registry_identity.as_ref(),
+                ctx.accounts.registry_enrolment.as_ref(),
+                ctx.accounts.registry_institution.as_ref(),
+                ctx.accounts.system_program.as_ref(),
+            ) {
+                require!(ctx.accounts.mustahik.dedup_hash != [0; 32], ZakaChainError::DedupHashMissing);
+                let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+                let signer = &[seeds];
+                let enrol_accounts = zakachain_core::cpi::accounts::EnrolIdentity {
+                    identity: identity.to_account_info(),
+                    enrolment: enrolment.to_account_info(),
+                    institution: institution.to_account_info(),
+                    authority: ctx.accounts.state.to_account_info(),
+                    mustahik_account: ctx.accounts.mustahik.to_account_info(),
+                    payer: ctx.accounts.approver.to_account_info(),
+                    system_program: system_program.to_account_info(),
+                };
+                zakachain_core::cpi::enrol_identity(
+                    CpiContext::new_with_signer(core_program.to_account_info(), enrol_accounts, signer),
+                    ctx.accounts.mustahik.dedup_hash,
+                )?;
+                ctx.accounts.mustahik.enrolment = enrolment.key();
+            }
+        }
+        let mustahik = &mut ctx.accounts.mustahik;
+        let timestamp = Clock::get()?.unix_timestamp;
+        mustahik.status = if approved {
+            MustahikStatus::Active
+        } else {
+            MustahikStatus::Rejected
+        };
+        mustahik.approver = ctx.accounts.approver.key();
+        mustahik.approved_at = timestamp;
+        if approved {
+            mustahik.eligible_until = eligibility_end(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+            let state = &mut ctx.accounts.state;
+            state.mustahik_count = state.mustahik_count.checked_add(1).unwrap();
+        }
+        emit!(MustahikReviewed {
+            mustahik: mustahik.wallet,
+            approver: mustahik.approver,
+            approved,
+            timestamp,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct WithdrawAmilFees<'info> {
-    #[account(mut)]
-    pub state: Account<'info, ZakaChainState>,
-    pub amil: Signer<'info>,
-    #[account(mut)]
-    pub program_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
+    /// Periodic re-survey of an active mustahik. A positive assessment renews
+    /// eligibility for another `ELIGIBILITY_PERIOD_YEARS`; a negative one
+    /// rejects the mustahik, who must apply again to receive distributions.
+    pub fn reverify_mustahik(
+        ctx: Context<ReverifyMustahik>,
+        assessment_hash: [u8; 32],
+        eligible: bool,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.officer.role == OfficerRole::Surveyor, ZakaChainError::Unauthorized);
+        let mustahik = &mut ctx.accounts.mustahik;
+        require!(mustahik.status == MustahikStatus::Active, ZakaChainError::InvalidMustahikStatus);
+        let timestamp = Clock::get()?.unix_timestamp;
+        mustahik.surveyor = ctx.accounts.surveyor.key();
+        mustahik.surveyed_at = timestamp;
+        mustahik.assessment_hash = assessment_hash;
+        mustahik.survey_recommended = eligible;
+        if eligible {
+            mustahik.eligible_until = eligibility_end(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        } else {
+            mustahik.status = MustahikStatus::Rejected;
+            mustahik.eligible_until = timestamp;
+            let state = &mut ctx.accounts.state;
+            state.mustahik_count = state.mustahik_count.checked_sub(1).unwrap();
+        }
+        emit!(MustahikReverified {
+            mustahik: mustahik.wallet,
+            surveyor: mustahik.surveyor,
+            assessment_hash,
+            eligible,
+            eligible_until: mustahik.eligible_until,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank: emits `MustahikEligibilityExpiring` for each active
+    /// mustahik in `remaining_accounts` whose eligibility ends within
+    /// `ELIGIBILITY_NOTICE_PERIOD` (or already has). Each expiry date is
+    /// announced once.
+    pub fn emit_eligibility_expiring<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EmitEligibilityExpiring<'info>>,
+    ) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        let notice_from = timestamp.checked_add(ELIGIBILITY_NOTICE_PERIOD).unwrap();
+        for mustahik_info in ctx.remaining_accounts.iter() {
+            require!(mustahik_info.is_writable, ZakaChainError::InvalidMustahik);
+            let mut mustahik = Account::<Mustahik>::try_from(mustahik_info)?;
+            require!(
+                mustahik.institution == ctx.accounts.state.key(),
+                ZakaChainError::InvalidMustahik
+            );
+            if mustahik.status != MustahikStatus::Active
+                || mustahik.eligible_until > notice_from
+                || mustahik.last_expiry_notice == mustahik.eligible_until
+            {
+                continue;
+            }
+            mustahik.last_expiry_notice = mustahik.eligible_until;
+            mustahik.exit(&crate::ID)?;
+            emit!(MustahikEligibilityExpiring {
+                mustahik: mustahik.wallet,
+                account: mustahik_info.key(),
+                eligible_until: mustahik.eligible_until,
+                timestamp,
+            });
+        }
+        Ok(())
+    }
+
+    /// Caps for one mustahik, on top of the caps of their asnaf. Zero means no cap.
+    pub fn set_mustahik_caps(ctx: Context<UpdateMustahik>, caps: DisbursementCaps) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let mustahik = &mut ctx.accounts.mustahik;
+        mustahik.caps = caps;
+        emit!(CapsUpdated {
+            mustahik: Some(mustahik.wallet),
+            asnaf: mustahik.asnaf,
+            caps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Caps for every mustahik of an asnaf. Zero means no cap.
+    pub fn set_asnaf_caps(ctx: Context<UpdateAsnafCaps>, asnaf: Asnaf, caps: DisbursementCaps) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.authority.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        ctx.accounts.state.asnaf_caps[asnaf as usize] = caps;
+        emit!(CapsUpdated {
+            mustahik: None,
+            asnaf,
+            caps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Checks a revealed NIK and salt against the mustahik's identity hash and
+    /// returns the result via return data. Auditors should run this as a
+    /// simulation so the NIK never lands on chain.
+    pub fn verify_identity(ctx: Context<VerifyIdentity>, nik: String, salt: [u8; 32]) -> Result<bool> {
+        Ok(identity::verify_identity(&ctx.accounts.mustahik.identity_hash, &nik, &salt))
+    }
+
+    /// Suspends or reinstates an approved mustahik without deregistering them.
+    pub fn update_mustahik(ctx: Context<UpdateMustahik>, is_active: bool) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let mustahik = &mut ctx.accounts.mustahik;
+        let (from, to) = if is_active {
+            (MustahikStatus::Suspended, MustahikStatus::Active)
+        } else {
+            (MustahikStatus::Active, MustahikStatus::Suspended)
+        };
+        require!(mustahik.status == from, ZakaChainError::InvalidMustahikStatus);
+        mustahik.status = to;
+        emit!(MustahikUpdated {
+            mustahik: mustahik.wallet,
+            status: to,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Deregisters a mustahik and closes their account. An enrolled mustahik's
+    /// registry enrolment is withdrawn as well, so the registry accounts are
+    /// required for them.
+    pub fn remove_mustahik(ctx: Context<RemoveMustahik>, mustahik_address: Pubkey) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        if ctx.accounts.mustahik.enrolment != Pubkey::default() {
+            let (Some(core_program), Some(identity), Some(enrolment), Some(institution)) = (
+                ctx.accounts.core_program.as_ref(),
+                ctx.accounts.registry_identity.as_ref(),
+                ctx.accounts.registry_enrolment.as_ref(),
+                ctx.accounts.registry_institution.as_ref(),
+            ) else {
+                return err!(ZakaChainError::InvalidRegistryAccounts);
+            };
+            require!(
+                enrolment.key() == ctx.accounts.mustahik.enrolment,
+                ZakaChainError::InvalidRegistryAccounts
+            );
+            let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+            let signer = &[seeds];
+            let withdraw_accounts = zakachain_core::cpi::accounts::WithdrawEnrolment {
+                identity: identity.to_account_info(),
+                enrolment: enrolment.to_account_info(),
+                institution: institution.to_account_info(),
+                authority: ctx.accounts.state.to_account_info(),
+                receiver: ctx.accounts.amil.to_account_info(),
+            };
+            zakachain_core::cpi::withdraw_enrolment(CpiContext::new_with_signer(
+                core_program.to_account_info(),
+                withdraw_accounts,
+                signer,
+            ))?;
+        }
+        let state = &mut ctx.accounts.state;
+        if ctx.accounts.mustahik.status.is_approved() {
+            state.mustahik_count = state.mustahik_count.checked_sub(1).unwrap();
+        }
+        emit!(MustahikRemoved {
+            mustahik: mustahik_address,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Collects a payment into the general pool, or into `fund` at the fund's
+    /// fee. Passing one of the payer's obligations on that fund counts the net
+    /// payment as an installment towards it, and passing a matching pool for
+    /// the fund adds the sponsor's match. Passing the payer's muzakki profile
+    /// credits the payment to it. The receipt covers only the payer's own
+    /// payment; a match stays in the fund if the payment is refunded.
+    pub fn collect_zakat(
+        ctx: Context<CollectZakat>,
+        amount: u64,
+        max_fee_percentage: u8,
+        expected_net_amount: Option<u64>,
+        payment_id: [u8; 16],
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            ctx.accounts.pool.is_some() == ctx.accounts.donor_match.is_some(),
+            ZakaChainError::InvalidMatchingPool
+        );
+        if let Some(obligation) = ctx.accounts.obligation.as_ref() {
+            let fund = ctx.accounts.fund.as_ref().ok_or(ZakaChainError::InvalidFund)?;
+            require!(ctx.accounts.profile.is_some(), ZakaChainError::InvalidProfile);
+            require!(obligation.fund == fund.key(), ZakaChainError::InvalidFund);
+            require!(obligation.completed_at == 0, ZakaChainError::ObligationCompleted);
+            require!(amount > 0, ZakaChainError::InvalidAmount);
+        }
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let state = &mut ctx.accounts.state;
+        let (net_amount, amil_fee) = match ctx.accounts.fund.as_mut() {
+            Some(fund) => {
+                require!(
+                    ctx.accounts.program_token_account.mint == fund.mint,
+                    ZakaChainError::InvalidTokenAccount
+                );
+                charge_fund(fund, &today, amount, max_fee_percentage)?
+            }
+            None => {
+                require!(
+                    state.amil_fee_percentage <= max_fee_percentage,
+                    ZakaChainError::FeeExceedsMaximum
+                );
+                let amil_fee = calculate_amil_fee(amount, state.amil_fee_percentage);
+                (amount.checked_sub(amil_fee).unwrap(), amil_fee)
+            }
+        };
+        if let Some(expected_net_amount) = expected_net_amount {
+            require!(net_amount == expected_net_amount, ZakaChainError::UnexpectedNetAmount);
+        }
+        if let Some(obligation) = ctx.accounts.obligation.as_ref() {
+            // Obligations count the zakat that reaches the fund, net of the fee
+            let remaining = obligation.amount.checked_sub(obligation.amount_paid).unwrap();
+            require!(net_amount <= remaining, ZakaChainError::ObligationOverpayment);
+        }
+        transfer_collection(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.payer_token_account.to_account_info(),
+            ctx.accounts.program_token_account.to_account_info(),
+            ctx.accounts.amil_token_account.to_account_info(),
+            ctx.accounts.payer.to_account_info(),
+            net_amount,
+            amil_fee,
+        )?;
+        let payer = ctx.accounts.payer.key();
+        let mut matched = 0;
+        if let Some(pool) = ctx.accounts.pool.as_mut() {
+            let fund = ctx.accounts.fund.as_mut().ok_or(ZakaChainError::InvalidFund)?;
+            require!(pool.fund == fund.key(), ZakaChainError::InvalidFund);
+            let pool_vault = ctx.accounts.pool_vault.as_ref().ok_or(ZakaChainError::InvalidTokenAccount)?;
+            require!(pool_vault.key() == pool.vault, ZakaChainError::InvalidTokenAccount);
+            let donor_match = ctx.accounts.donor_match.as_mut().ok_or(ZakaChainError::InvalidMatchingPool)?;
+            donor_match.pool = pool.key();
+            donor_match.donor = payer;
+            donor_match.bump = ctx.bumps.donor_match;
+            matched = match_donation(
+                pool,
+                pool_vault,
+                donor_match,
+                ctx.accounts.program_token_account.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount,
+                timestamp,
+            )?;
+            if matched > 0 {
+                fund.total_collected = fund.total_collected.checked_add(matched).unwrap();
+                emit!(DonationMatched {
+                    pool: pool.key(),
+                    donor: payer,
+                    fund: fund.key(),
+                    donation: amount,
+                    matched,
+                    timestamp,
+                });
+            }
+        }
+        record_collection(state, today, timestamp, net_amount.checked_add(matched).unwrap(), amil_fee);
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.set_inner(issue_receipt(
+            state,
+            payer,
+            payment_id,
+            0,
+            net_amount,
+            amil_fee,
+            timestamp,
+            today,
+            ctx.bumps.receipt,
+        ));
+        if let Some(profile) = ctx.accounts.profile.as_mut() {
+            profile.total_paid = profile.total_paid.checked_add(net_amount).unwrap();
+            profile.last_paid_at = timestamp;
+            receipt.profile_credited = true;
+        }
+        if let Some(fund) = ctx.accounts.fund.as_ref() {
+            receipt.fund = fund.key();
+            emit!(FundContribution {
+                payer,
+                fund: fund.key(),
+                amount: net_amount,
+                amil_fee,
+                timestamp,
+            });
+        }
+        if let Some(obligation) = ctx.accounts.obligation.as_mut() {
+            obligation.amount_paid = obligation.amount_paid.checked_add(net_amount).unwrap();
+            let remaining = obligation.amount.checked_sub(obligation.amount_paid).unwrap();
+            receipt.obligation = obligation.key();
+            emit!(ObligationInstallmentPaid {
+                obligation: obligation.key(),
+                payer,
+                amount: net_amount,
+                amil_fee,
+                remaining,
+                timestamp,
+            });
+            if remaining == 0 {
+                obligation.completed_at = timestamp;
+                let profile = ctx.accounts.profile.as_mut().ok_or(ZakaChainError::InvalidProfile)?;
+                complete_haul(
+                    profile,
+                    obligation.haul_year,
+                    &ctx.accounts.hijri_calendar.month_starts,
+                    timestamp,
+                );
+                emit!(ObligationCompleted {
+                    obligation: obligation.key(),
+                    muzakki: obligation.muzakki,
+                    haul_year: obligation.haul_year,
+                    amount: obligation.amount,
+                    timestamp,
+                });
+            }
+        }
+        if let Some(agent) = ctx.accounts.agent.as_mut() {
+            require!(agent.is_active, ZakaChainError::AgentInactive);
+            let commission = calculate_agent_commission(amil_fee, agent.commission_bps);
+            agent.total_collected = agent.total_collected.checked_add(net_amount).unwrap();
+            agent.total_amil_fees = agent.total_amil_fees.checked_add(amil_fee).unwrap();
+            agent.commission_accrued = agent.commission_accrued.checked_add(commission).unwrap();
+            receipt.agent = agent.key();
+            receipt.agent_commission = commission;
+            emit!(AgentCollection {
+                agent: agent.key(),
+                receipt: receipt.key(),
+                amount: net_amount,
+                commission,
+                timestamp,
+            });
+        }
+        ctx.accounts
+            .payment
+            .set_inner(mark_payment(payer, payment_id, receipt.key(), timestamp, ctx.bumps.payment));
+        emit!(ZakatCollected {
+            payer,
+            receipt: receipt.key(),
+            payment_id,
+            amount: net_amount,
+            amil_fee,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Gasless variant of `collect_zakat`: the relayer pays transaction fees and
+    /// rent, the donor only signs as token authority. Up to `max_relayer_fee`
+    /// of the donation reimburses the relayer; the amil fee applies to the rest.
+    pub fn collect_zakat_relayed(
+        ctx: Context<CollectZakatRelayed>,
+        amount: u64,
+        relayer_fee: u64,
+        max_fee_percentage: u8,
+        expected_net_amount: Option<u64>,
+        payment_id: [u8; 16],
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(relayer_fee <= state.max_relayer_fee, ZakaChainError::RelayerFeeTooHigh);
+        require!(relayer_fee < amount, ZakaChainError::RelayerFeeTooHigh);
+        require!(
+            state.amil_fee_percentage <= max_fee_percentage,
+            ZakaChainError::FeeExceedsMaximum
+        );
+        let donation = amount.checked_sub(relayer_fee).unwrap();
+        let amil_fee = calculate_amil_fee(donation, state.amil_fee_percentage);
+        let net_amount = donation.checked_sub(amil_fee).unwrap();
+        if let Some(expected_net_amount) = expected_net_amount {
+            require!(net_amount == expected_net_amount, ZakaChainError::UnexpectedNetAmount);
+        }
+        if relayer_fee > 0 {
+            let transfer_accounts = Transfer {
+                from: ctx.accounts.donor_token_account.to_account_info(),
+                to: ctx.accounts.relayer_token_account.to_account_info(),
+                authority: ctx.accounts.donor.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
+                relayer_fee,
+            )?;
+        }
+        transfer_collection(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.donor_token_account.to_account_info(),
+            ctx.accounts.program_token_account.to_account_info(),
+            ctx.accounts.amil_token_account.to_account_info(),
+            ctx.accounts.donor.to_account_info(),
+            net_amount,
+            amil_fee,
+        )?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        record_collection(state, today, timestamp, net_amount, amil_fee);
+        let donor = ctx.accounts.donor.key();
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.set_inner(issue_receipt(
+            state,
+            donor,
+            payment_id,
+            0,
+            net_amount,
+            amil_fee,
+            timestamp,
+            today,
+            ctx.bumps.receipt,
+        ));
+        ctx.accounts
+            .payment
+            .set_inner(mark_payment(donor, payment_id, receipt.key(), timestamp, ctx.bumps.payment));
+        emit!(ZakatCollected {
+            payer: donor,
+            receipt: receipt.key(),
+            payment_id,
+            amount: net_amount,
+            amil_fee,
+            timestamp,
+        });
+        emit!(RelayerReimbursed {
+            receipt: receipt.key(),
+            donor,
+            relayer: ctx.accounts.relayer.key(),
+            relayer_fee,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn register_integration(
+        ctx: Context<RegisterIntegration>,
+        authority: Pubkey,
+        program_id: Pubkey,
+        name: String,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(name.len() <= MAX_INTEGRATION_NAME_LEN, ZakaChainError::IntegrationNameTooLong);
+        let integration = &mut ctx.accounts.integration;
+        integration.authority = authority;
+        integration.program_id = program_id;
+        integration.name = name;
+        integration.is_active = true;
+        integration.total_collected = 0;
+        integration.created_at = Clock::get()?.unix_timestamp;
+        integration.bump = ctx.bumps.integration;
+        emit!(IntegrationRegistered {
+            integration: integration.key(),
+            authority,
+            program_id,
+            timestamp: integration.created_at,
+        });
+        Ok(())
+    }
+
+    pub fn set_integration_active(ctx: Context<UpdateIntegration>, is_active: bool) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        ctx.accounts.integration.is_active = is_active;
+        Ok(())
+    }
+
+    /// CPI entry point for registered protocols: pays zakat from a user's token
+    /// account using the allowance the user delegated to the protocol's
+    /// authority PDA. The receipt belongs to the user and records the protocol.
+    /// The transaction's top-level instruction must be the integration's
+    /// registered program, so protocols must call this directly rather than
+    /// through a router.
+    pub fn collect_zakat_for(
+        ctx: Context<CollectZakatFor>,
+        amount: u64,
+        max_fee_percentage: u8,
+        payment_id: [u8; 16],
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.integration.is_active, ZakaChainError::IntegrationInactive);
+        let calling_instruction = instructions_sysvar::get_instruction_relative(0, &ctx.accounts.instructions)?;
+        require!(
+            calling_instruction.program_id == ctx.accounts.integration.program_id,
+            ZakaChainError::IntegrationProgramMismatch
+        );
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        require!(
+            state.amil_fee_percentage <= max_fee_percentage,
+            ZakaChainError::FeeExceedsMaximum
+        );
+        let amil_fee = calculate_amil_fee(amount, state.amil_fee_percentage);
+        let net_amount = amount.checked_sub(amil_fee).unwrap();
+        transfer_collection(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.program_token_account.to_account_info(),
+            ctx.accounts.amil_token_account.to_account_info(),
+            ctx.accounts.protocol_authority.to_account_info(),
+            net_amount,
+            amil_fee,
+        )?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        record_collection(state, today, timestamp, net_amount, amil_fee);
+        let integration = &mut ctx.accounts.integration;
+        integration.total_collected = integration.total_collected.checked_add(net_amount).unwrap();
+        let user = ctx.accounts.user.key();
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.set_inner(issue_receipt(
+            state,
+            user,
+            payment_id,
+            0,
+            net_amount,
+            amil_fee,
+            timestamp,
+            today,
+            ctx.bumps.receipt,
+        ));
+        receipt.integration = integration.key();
+        ctx.accounts
+            .payment
+            .set_inner(mark_payment(user, payment_id, receipt.key(), timestamp, ctx.bumps.payment));
+        emit!(ZakatCollected {
+            payer: user,
+            receipt: receipt.key(),
+            payment_id,
+            amount: net_amount,
+            amil_fee,
+            timestamp,
+        });
+        emit!(IntegrationCollection {
+            integration: integration.key(),
+            program_id: integration.program_id,
+            user,
+            receipt: receipt.key(),
+            amount: net_amount,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Collects zakat deducted from salaries: the employer pays once and each
+    /// employee gets a receipt and a credit on their muzakki profile. For every
+    /// entry in `amounts`, `remaining_accounts` holds the employee's profile and
+    /// the receipt PDA `["receipt", employer, payment_id, entry index]`, both
+    /// writable.
+    pub fn collect_payroll<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CollectPayroll<'info>>,
+        amounts: Vec<u64>,
+        max_fee_percentage: u8,
+        payment_id: [u8; 16],
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            !amounts.is_empty() && amounts.len() <= MAX_PAYROLL_ENTRIES,
+            ZakaChainError::InvalidPayroll
+        );
+        require!(
+            ctx.remaining_accounts.len() == amounts.len() * 2,
+            ZakaChainError::InvalidPayroll
+        );
+        require!(
+            ctx.accounts.state.amil_fee_percentage <= max_fee_percentage,
+            ZakaChainError::FeeExceedsMaximum
+        );
+        let employer = ctx.accounts.employer.key();
+        let fee_percentage = ctx.accounts.state.amil_fee_percentage;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let mut first_receipt = Pubkey::default();
+        let mut total_net: u64 = 0;
+        let mut total_fee: u64 = 0;
+        for (index, (amount, entry)) in amounts.iter().zip(ctx.remaining_accounts.chunks(2)).enumerate() {
+            require!(*amount > 0, ZakaChainError::InvalidAmount);
+            let (profile_info, receipt_info) = (&entry[0], &entry[1]);
+            require!(profile_info.is_writable, ZakaChainError::InvalidProfile);
+            let mut profile = Account::<MuzakkiProfile>::try_from(profile_info)?;
+            let index = index as u8;
+            let (receipt_key, receipt_bump) = Pubkey::find_program_address(
+                &[b"receipt", employer.as_ref(), payment_id.as_ref(), &[index]],
+                &crate::ID,
+            );
+            require!(receipt_info.key() == receipt_key, ZakaChainError::InvalidReceipt);
+            create_pda_account(
+                &ctx.accounts.employer.to_account_info(),
+                receipt_info,
+                &ctx.accounts.system_program.to_account_info(),
+                8 + Receipt::LEN,
+                &[b"receipt", employer.as_ref(), payment_id.as_ref(), &[index], &[receipt_bump]],
+            )?;
+            let amil_fee = calculate_amil_fee(*amount, fee_percentage);
+            let net_amount = amount.checked_sub(amil_fee).unwrap();
+            let mut receipt = issue_receipt(
+                &mut ctx.accounts.state,
+                employer,
+                payment_id,
+                index,
+                net_amount,
+                amil_fee,
+                timestamp,
+                today,
+                receipt_bump,
+            );
+            receipt.muzakki = profile.owner;
+            receipt.profile_credited = true;
+            receipt.try_serialize(&mut &mut receipt_info.try_borrow_mut_data()?[..])?;
+            profile.total_paid = profile.total_paid.checked_add(net_amount).unwrap();
+            profile.last_paid_at = timestamp;
+            profile.exit(&crate::ID)?;
+            if first_receipt == Pubkey::default() {
+                first_receipt = receipt_key;
+            }
+            total_net = total_net.checked_add(net_amount).unwrap();
+            total_fee = total_fee.checked_add(amil_fee).unwrap();
+            emit!(PayrollContribution {
+                employer,
+                muzakki: profile.owner,
+                receipt: receipt_key,
+                amount: net_amount,
+                amil_fee,
+                timestamp,
+            });
+        }
+        transfer_collection(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.employer_token_account.to_account_info(),
+            ctx.accounts.program_token_account.to_account_info(),
+            ctx.accounts.amil_token_account.to_account_info(),
+            ctx.accounts.employer.to_account_info(),
+            total_net,
+            total_fee,
+        )?;
+        let state = &mut ctx.accounts.state;
+        record_collection(state, today, timestamp, total_net, total_fee);
+        ctx.accounts
+            .payment
+            .set_inner(mark_payment(employer, payment_id, first_receipt, timestamp, ctx.bumps.payment));
+        emit!(PayrollCollected {
+            employer,
+            payment_id,
+            employees: amounts.len() as u16,
+            amount: total_net,
+            amil_fee: total_fee,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Collects one payment split across several funds, with one receipt per
+    /// part. Each fund and the part's receipt, seeded by the payment id and the
+    /// part's index, are passed as writable `remaining_accounts` pairs in the
+    /// same order as `parts`.
+    pub fn collect_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CollectSplit<'info>>,
+        parts: Vec<FundPart>,
+        max_fee_percentage: u8,
+        expected_net_amount: Option<u64>,
+        payment_id: [u8; 16],
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            !parts.is_empty() && parts.len() <= MAX_SPLIT_PARTS,
+            ZakaChainError::InvalidSplit
+        );
+        require!(
+            ctx.remaining_accounts.len() == parts.len() * 2,
+            ZakaChainError::InvalidSplit
+        );
+        for (i, part) in parts.iter().enumerate() {
+            require!(
+                parts[..i].iter().all(|p| p.fund != part.fund),
+                ZakaChainError::InvalidSplit
+            );
+        }
+        let payer = ctx.accounts.payer.key();
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let mut first_receipt = Pubkey::default();
+        let mut total_net: u64 = 0;
+        let mut total_fee: u64 = 0;
+        for (index, (part, entry)) in parts.iter().zip(ctx.remaining_accounts.chunks(2)).enumerate() {
+            require!(part.amount > 0, ZakaChainError::InvalidAmount);
+            let (fund_info, receipt_info) = (&entry[0], &entry[1]);
+            require!(
+                fund_info.key() == part.fund && fund_info.is_writable,
+                ZakaChainError::InvalidFund
+            );
+            let mut fund = Account::<Fund>::try_from(fund_info)?;
+            require!(
+                ctx.accounts.program_token_account.mint == fund.mint,
+                ZakaChainError::InvalidTokenAccount
+            );
+            let (net_amount, amil_fee) = charge_fund(&mut fund, &today, part.amount, max_fee_percentage)?;
+            fund.exit(&crate::ID)?;
+            let index = index as u8;
+            let (receipt_key, receipt_bump) = Pubkey::find_program_address(
+                &[b"receipt", payer.as_ref(), payment_id.as_ref(), &[index]],
+                &crate::ID,
+            );
+            require!(receipt_info.key() == receipt_key, ZakaChainError::InvalidReceipt);
+            create_pda_account(
+                &ctx.accounts.payer.to_account_info(),
+                receipt_info,
+                &ctx.accounts.system_program.to_account_info(),
+                8 + Receipt::LEN,
+                &[b"receipt", payer.as_ref(), payment_id.as_ref(), &[index], &[receipt_bump]],
+            )?;
+            let mut receipt = issue_receipt(
+                &mut ctx.accounts.state,
+                payer,
+                payment_id,
+                index,
+                net_amount,
+                amil_fee,
+                timestamp,
+                today,
+                receipt_bump,
+            );
+            receipt.fund = part.fund;
+            receipt.try_serialize(&mut &mut receipt_info.try_borrow_mut_data()?[..])?;
+            if first_receipt == Pubkey::default() {
+                first_receipt = receipt_key;
+            }
+            total_net = total_net.checked_add(net_amount).unwrap();
+            total_fee = total_fee.checked_add(amil_fee).unwrap();
+            emit!(FundContribution {
+                payer,
+                fund: part.fund,
+                amount: net_amount,
+                amil_fee,
+                timestamp,
+            });
+            emit!(ZakatCollected {
+                payer,
+                receipt: receipt_key,
+                payment_id,
+                amount: net_amount,
+                amil_fee,
+                timestamp,
+            });
+        }
+        if let Some(expected_net_amount) = expected_net_amount {
+            require!(total_net == expected_net_amount, ZakaChainError::UnexpectedNetAmount);
+        }
+        transfer_collection(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.payer_token_account.to_account_info(),
+            ctx.accounts.program_token_account.to_account_info(),
+            ctx.accounts.amil_token_account.to_account_info(),
+            ctx.accounts.payer.to_account_info(),
+            total_net,
+            total_fee,
+        )?;
+        record_collection(&mut ctx.accounts.state, today, timestamp, total_net, total_fee);
+        ctx.accounts
+            .payment
+            .set_inner(mark_payment(payer, payment_id, first_receipt, timestamp, ctx.bumps.payment));
+        Ok(())
+    }
+
+    pub fn create_invoice(
+        ctx: Context<CreateInvoice>,
+        payer: Pubkey,
+        amount: u64,
+        due_date: i64,
+        reference: String,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        require!(
+            reference.len() <= MAX_INVOICE_REFERENCE_LEN,
+            ZakaChainError::ReferenceTooLong
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(due_date > timestamp, ZakaChainError::InvalidDueDate);
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.id = state.invoice_count;
+        invoice.payer = payer;
+        invoice.fund = ctx.accounts.fund.key();
+        invoice.amount = amount;
+        invoice.amount_paid = 0;
+        invoice.due_date = due_date;
+        invoice.reference = reference;
+        invoice.status = InvoiceStatus::Open;
+        invoice.created_at = timestamp;
+        invoice.bump = ctx.bumps.invoice;
+        state.invoice_count = state.invoice_count.checked_add(1).unwrap();
+        emit!(InvoiceCreated {
+            invoice: invoice.key(),
+            payer,
+            fund: invoice.fund,
+            amount,
+            due_date,
+            reference: invoice.reference.clone(),
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pays all or part of an invoice through the regular collection path,
+    /// charging the fee of the invoice's fund. Each installment carries its own
+    /// payment id and gets a receipt, so it can be retried safely and refunded.
+    pub fn pay_invoice(
+        ctx: Context<PayInvoice>,
+        amount: u64,
+        max_fee_percentage: u8,
+        payment_id: [u8; 16],
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        let invoice = &ctx.accounts.invoice;
+        let payer = ctx.accounts.payer.key();
+        require!(payer == invoice.payer, ZakaChainError::Unauthorized);
+        require!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::Open | InvoiceStatus::PartiallyPaid | InvoiceStatus::Overdue
+            ),
+            ZakaChainError::InvoiceNotPayable
+        );
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        let remaining = invoice.amount.checked_sub(invoice.amount_paid).unwrap();
+        require!(amount <= remaining, ZakaChainError::InvoiceOverpayment);
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let (net_amount, amil_fee) = charge_fund(&mut ctx.accounts.fund, &today, amount, max_fee_percentage)?;
+        transfer_collection(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.payer_token_account.to_account_info(),
+            ctx.accounts.program_token_account.to_account_info(),
+            ctx.accounts.amil_token_account.to_account_info(),
+            ctx.accounts.payer.to_account_info(),
+            net_amount,
+            amil_fee,
+        )?;
+        let state = &mut ctx.accounts.state;
+        record_collection(state, today, timestamp, net_amount, amil_fee);
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.set_inner(issue_receipt(
+            state,
+            payer,
+            payment_id,
+            0,
+            net_amount,
+            amil_fee,
+            timestamp,
+            today,
+            ctx.bumps.receipt,
+        ));
+        receipt.fund = ctx.accounts.fund.key();
+        receipt.invoice = ctx.accounts.invoice.key();
+        ctx.accounts
+            .payment
+            .set_inner(mark_payment(payer, payment_id, receipt.key(), timestamp, ctx.bumps.payment));
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.amount_paid = invoice.amount_paid.checked_add(amount).unwrap();
+        if invoice.amount_paid == invoice.amount {
+            invoice.status = InvoiceStatus::Paid;
+        } else if invoice.status == InvoiceStatus::Open {
+            invoice.status = InvoiceStatus::PartiallyPaid;
+        }
+        emit!(ZakatCollected {
+            payer,
+            receipt: receipt.key(),
+            payment_id,
+            amount: net_amount,
+            amil_fee,
+            timestamp,
+        });
+        emit!(InvoicePaid {
+            invoice: invoice.key(),
+            payer: invoice.payer,
+            amount: net_amount,
+            amil_fee,
+            remaining: invoice.amount.checked_sub(invoice.amount_paid).unwrap(),
+            status: invoice.status,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_invoice(ctx: Context<UpdateInvoice>) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.authority.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let invoice = &mut ctx.accounts.invoice;
+        require!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::Open | InvoiceStatus::PartiallyPaid | InvoiceStatus::Overdue
+            ),
+            ZakaChainError::InvoiceNotPayable
+        );
+        invoice.status = InvoiceStatus::Cancelled;
+        emit!(InvoiceCancelled {
+            invoice: invoice.key(),
+            amount_paid: invoice.amount_paid,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Permissionless: anyone may flag an unpaid invoice once its due date has passed.
+    pub fn mark_invoice_overdue(ctx: Context<UpdateInvoice>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        require!(
+            matches!(invoice.status, InvoiceStatus::Open | InvoiceStatus::PartiallyPaid),
+            ZakaChainError::InvoiceNotPayable
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp > invoice.due_date, ZakaChainError::InvoiceNotOverdue);
+        invoice.status = InvoiceStatus::Overdue;
+        emit!(InvoiceOverdue {
+            invoice: invoice.key(),
+            remaining: invoice.amount.checked_sub(invoice.amount_paid).unwrap(),
+            timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn register_muzakki(ctx: Context<RegisterMuzakki>, haul_start: i64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(haul_start <= timestamp, ZakaChainError::InvalidHaulStart);
+        let started_on = hijri::from_unix_timestamp(haul_start, &ctx.accounts.hijri_calendar.month_starts);
+        let profile = &mut ctx.accounts.profile;
+        profile.owner = ctx.accounts.owner.key();
+        profile.set_haul_start(haul_start, started_on);
+        profile.last_completed_at = 0;
+        profile.completed_haul_year = 0;
+        profile.last_reminded_due = 0;
+        profile.created_at = timestamp;
+        profile.total_paid = 0;
+        profile.last_paid_at = 0;
+        profile.bump = ctx.bumps.profile;
+        emit!(MuzakkiRegistered {
+            muzakki: profile.owner,
+            haul_start,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Restarts the haul, e.g. after wealth dropped below nisab and reached it again.
+    pub fn update_haul_start(ctx: Context<UpdateMuzakki>, haul_start: i64) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(haul_start <= timestamp, ZakaChainError::InvalidHaulStart);
+        let started_on = hijri::from_unix_timestamp(haul_start, &ctx.accounts.hijri_calendar.month_starts);
+        let profile = &mut ctx.accounts.profile;
+        profile.set_haul_start(haul_start, started_on);
+        emit!(HaulStartUpdated {
+            muzakki: profile.owner,
+            haul_start,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank: emits `ZakatDue` for each muzakki profile in
+    /// `remaining_accounts` whose haul anniversary has passed without the
+    /// obligation for that haul being completed. Each profile is reminded once
+    /// per haul; the haul is one Hijri year, so the anniversary is the same
+    /// Hijri date a year later.
+    pub fn emit_zakat_due<'info>(ctx: Context<'_, '_, 'info, 'info, EmitZakatDue<'info>>) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        let month_starts = &ctx.accounts.hijri_calendar.month_starts;
+        for profile_info in ctx.remaining_accounts.iter() {
+            require!(profile_info.is_writable, ZakaChainError::InvalidProfile);
+            let mut profile = Account::<MuzakkiProfile>::try_from(profile_info)?;
+            let due_at = haul_due_at(&profile, month_starts);
+            let due_year = profile.haul_start_date().year + 1;
+            if timestamp < due_at
+                || profile.last_reminded_due == due_at
+                || profile.completed_haul_year >= due_year
+            {
+                continue;
+            }
+            profile.last_reminded_due = due_at;
+            profile.exit(&crate::ID)?;
+            emit!(ZakatDue {
+                muzakki: profile.owner,
+                profile: profile_info.key(),
+                haul_start: profile.haul_start,
+                due_at,
+                timestamp,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn register_obligation(ctx: Context<RegisterObligation>, haul_year: u16, amount: u64) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        let timestamp = Clock::get()?.unix_timestamp;
+        let obligation = &mut ctx.accounts.obligation;
+        obligation.muzakki = ctx.accounts.muzakki.key();
+        obligation.fund = ctx.accounts.fund.key();
+        obligation.haul_year = haul_year;
+        obligation.amount = amount;
+        obligation.amount_paid = 0;
+        obligation.created_at = timestamp;
+        obligation.completed_at = 0;
+        obligation.bump = ctx.bumps.obligation;
+        emit!(ObligationRegistered {
+            obligation: obligation.key(),
+            muzakki: obligation.muzakki,
+            fund: obligation.fund,
+            haul_year,
+            amount,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Returns the amount still due on an obligation via return data.
+    pub fn get_obligation_remaining(ctx: Context<GetObligationRemaining>) -> Result<u64> {
+        let obligation = &ctx.accounts.obligation;
+        Ok(obligation.amount.checked_sub(obligation.amount_paid).unwrap())
+    }
+
+    pub fn create_matching_pool(
+        ctx: Context<CreateMatchingPool>,
+        match_ratio_bps: u16,
+        per_donor_cap: u64,
+        total_cap: u64,
+        end_timestamp: i64,
+        deposit: u64,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(
+            match_ratio_bps > 0 && match_ratio_bps <= MAX_MATCH_RATIO_BPS,
+            ZakaChainError::InvalidMatchingPool
+        );
+        require!(per_donor_cap > 0 && total_cap > 0 && deposit > 0, ZakaChainError::InvalidMatchingPool);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(end_timestamp > timestamp, ZakaChainError::InvalidMatchingPool);
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.sponsor_token_account.to_account_info(),
+            to: ctx.accounts.pool_vault.to_account_info(),
+            authority: ctx.accounts.sponsor.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
+            deposit,
+        )?;
+        let pool = &mut ctx.accounts.pool;
+        pool.sponsor = ctx.accounts.sponsor.key();
+        pool.fund = ctx.accounts.fund.key();
+        pool.vault = ctx.accounts.pool_vault.key();
+        pool.match_ratio_bps = match_ratio_bps;
+        pool.per_donor_cap = per_donor_cap;
+        pool.total_cap = total_cap;
+        pool.total_matched = 0;
+        pool.end_timestamp = end_timestamp;
+        pool.bump = ctx.bumps.pool;
+        emit!(MatchingPoolCreated {
+            pool: pool.key(),
+            sponsor: pool.sponsor,
+            fund: pool.fund,
+            match_ratio_bps,
+            per_donor_cap,
+            total_cap,
+            end_timestamp,
+            deposit,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Returns whatever is left in the pool vault to the sponsor once the
+    /// campaign has ended, and closes the pool and its vault.
+    pub fn close_matching_pool(ctx: Context<CloseMatchingPool>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp >= pool.end_timestamp, ZakaChainError::MatchingPoolActive);
+        let returned = ctx.accounts.pool_vault.amount;
+        let pool_seeds: &[&[u8]] = &[
+            b"matching_pool",
+            pool.sponsor.as_ref(),
+            pool.fund.as_ref(),
+            &[pool.bump],
+        ];
+        let signer = &[pool_seeds];
+        if returned > 0 {
+            let transfer_accounts = Transfer {
+                from: ctx.accounts.pool_vault.to_account_info(),
+                to: ctx.accounts.sponsor_token_account.to_account_info(),
+                authority: ctx.accounts.pool.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+                returned,
+            )?;
+        }
+        let close_accounts = CloseAccount {
+            account: ctx.accounts.pool_vault.to_account_info(),
+            destination: ctx.accounts.sponsor.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            close_accounts,
+            signer,
+        ))?;
+        let pool = &ctx.accounts.pool;
+        emit!(MatchingPoolClosed {
+            pool: pool.key(),
+            sponsor: pool.sponsor,
+            total_matched: pool.total_matched,
+            returned,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn create_campaign(
+        ctx: Context<CreateCampaign>,
+        title: String,
+        target: u64,
+        deadline: i64,
+        beneficiaries: Vec<Beneficiary>,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(title.len() <= MAX_CAMPAIGN_TITLE_LEN, ZakaChainError::CampaignTitleTooLong);
+        require!(target > 0, ZakaChainError::InvalidAmount);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(deadline > timestamp, ZakaChainError::InvalidDueDate);
+        require!(
+            !beneficiaries.is_empty() && beneficiaries.len() <= MAX_BENEFICIARIES,
+            ZakaChainError::InvalidBeneficiaries
+        );
+        require!(
+            beneficiaries.iter().map(|b| b.share_bps as u32).sum::<u32>() == 10_000,
+            ZakaChainError::InvalidBeneficiaries
+        );
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.id = state.campaign_count;
+        campaign.title = title;
+        campaign.target = target;
+        campaign.deadline = deadline;
+        campaign.beneficiaries = beneficiaries;
+        campaign.vault = ctx.accounts.campaign_vault.key();
+        campaign.raised = 0;
+        campaign.refunded = 0;
+        campaign.released = false;
+        campaign.bump = ctx.bumps.campaign;
+        state.campaign_count = state.campaign_count.checked_add(1).unwrap();
+        emit!(CampaignCreated {
+            campaign: campaign.key(),
+            title: campaign.title.clone(),
+            target,
+            deadline,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Adds a donation to the campaign escrow. The payment id guards against
+    /// a retried donation being escrowed twice.
+    pub fn donate_to_campaign(ctx: Context<DonateToCampaign>, amount: u64, payment_id: [u8; 16]) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(
+            !campaign.released && timestamp < campaign.deadline,
+            ZakaChainError::CampaignNotActive
+        );
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.donor_token_account.to_account_info(),
+            to: ctx.accounts.campaign_vault.to_account_info(),
+            authority: ctx.accounts.donor.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
+            amount,
+        )?;
+        campaign.raised = campaign.raised.checked_add(amount).unwrap();
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.campaign = campaign.key();
+        contribution.donor = ctx.accounts.donor.key();
+        contribution.amount = contribution.amount.checked_add(amount).unwrap();
+        contribution.bump = ctx.bumps.contribution;
+        ctx.accounts.payment.set_inner(mark_payment(
+            contribution.donor,
+            payment_id,
+            contribution.key(),
+            timestamp,
+            ctx.bumps.payment,
+        ));
+        emit!(CampaignDonation {
+            campaign: campaign.key(),
+            donor: contribution.donor,
+            amount,
+            raised: campaign.raised,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Permissionless once the target is reached: pays the escrow out to the
+    /// beneficiaries by share. Their token accounts are passed as writable
+    /// `remaining_accounts` in the campaign's beneficiary order.
+    pub fn release_campaign<'info>(ctx: Context<'_, '_, 'info, 'info, ReleaseCampaign<'info>>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        require!(!campaign.released, ZakaChainError::CampaignNotActive);
+        require!(campaign.raised >= campaign.target, ZakaChainError::CampaignTargetNotReached);
+        require!(
+            ctx.remaining_accounts.len() == campaign.beneficiaries.len(),
+            ZakaChainError::InvalidBeneficiaries
+        );
+        let total = ctx.accounts.campaign_vault.amount;
+        let id_bytes = campaign.id.to_le_bytes();
+        let campaign_seeds: &[&[u8]] = &[b"campaign", id_bytes.as_ref(), &[campaign.bump]];
+        let signer = &[campaign_seeds];
+        let mut paid: u64 = 0;
+        let last = campaign.beneficiaries.len() - 1;
+        for (i, (beneficiary, token_info)) in campaign
+            .beneficiaries
+            .iter()
+            .zip(ctx.remaining_accounts.iter())
+            .enumerate()
+        {
+            let token_account = Account::<TokenAccount>::try_from(token_info)?;
+            require!(
+                token_account.owner == beneficiary.wallet && token_info.is_writable,
+                ZakaChainError::InvalidBeneficiaries
+            );
+            // The last beneficiary takes the rounding remainder
+            let share = if i == last {
+                total.checked_sub(paid).unwrap()
+            } else {
+                (total as u128)
+                    .checked_mul(beneficiary.share_bps as u128)
+                    .unwrap()
+                    .checked_div(10_000)
+                    .unwrap() as u64
+            };
+            let transfer_accounts = Transfer {
+                from: ctx.accounts.campaign_vault.to_account_info(),
+                to: token_info.clone(),
+                authority: ctx.accounts.campaign.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+                share,
+            )?;
+            paid = paid.checked_add(share).unwrap();
+        }
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.released = true;
+        emit!(CampaignReleased {
+            campaign: campaign.key(),
+            amount: total,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// After a missed deadline, returns the donor's pro rata share of the escrow.
+    pub fn refund_contribution(ctx: Context<RefundContribution>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(
+            !campaign.released && timestamp >= campaign.deadline && campaign.raised < campaign.target,
+            ZakaChainError::CampaignNotRefundable
+        );
+        let contribution = &ctx.accounts.contribution;
+        require!(contribution.amount > 0, ZakaChainError::CampaignNotRefundable);
+        let outstanding = campaign.raised.checked_sub(campaign.refunded).unwrap();
+        let refund = (contribution.amount as u128)
+            .checked_mul(ctx.accounts.campaign_vault.amount as u128)
+            .unwrap()
+            .checked_div(outstanding as u128)
+            .unwrap() as u64;
+        let id_bytes = campaign.id.to_le_bytes();
+        let campaign_seeds: &[&[u8]] = &[b"campaign", id_bytes.as_ref(), &[campaign.bump]];
+        let signer = &[campaign_seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.campaign_vault.to_account_info(),
+            to: ctx.accounts.donor_token_account.to_account_info(),
+            authority: ctx.accounts.campaign.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            refund,
+        )?;
+        let contributed = contribution.amount;
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.refunded = campaign.refunded.checked_add(contributed).unwrap();
+        ctx.accounts.contribution.amount = 0;
+        emit!(CampaignRefunded {
+            campaign: campaign.key(),
+            donor: ctx.accounts.donor.key(),
+            contributed,
+            refunded: refund,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Returns a payment to its payer and reverses it in the lifetime totals,
+    /// the fund's totals, the attributed agent's totals and accrued commission,
+    /// the muzakki profile it was credited to, and the invoice or obligation
+    /// it paid. The current Hijri month's totals are
+    /// reversed when the payment was made in that month; months already closed
+    /// by `HijriPeriodClosed` keep their reported figures, and indexers correct
+    /// them from `ZakatRefunded`.
+    pub fn refund_zakat(ctx: Context<RefundZakat>) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        let state = &mut ctx.accounts.state;
+        let receipt = &mut ctx.accounts.receipt;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(!receipt.refunded, ZakaChainError::AlreadyRefunded);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(
+            timestamp.checked_sub(receipt.timestamp).unwrap() <= state.refund_window,
+            ZakaChainError::RefundWindowExpired
+        );
+        let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+        let signer = &[seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.program_token_account.to_account_info(),
+            to: ctx.accounts.payer_token_account.to_account_info(),
+            authority: state_info,
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            receipt.amount,
+        )?;
+        if receipt.amil_fee > 0 {
+            let transfer_accounts = Transfer {
+                from: ctx.accounts.amil_token_account.to_account_info(),
+                to: ctx.accounts.payer_token_account.to_account_info(),
+                authority: ctx.accounts.amil.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_accounts),
+                receipt.amil_fee,
+            )?;
+        }
+        reverse_collection(state, receipt.paid_year, receipt.paid_month, receipt.amount, receipt.amil_fee)?;
+        if receipt.agent != Pubkey::default() {
+            let agent = ctx.accounts.agent.as_mut().ok_or(ZakaChainError::InvalidAgent)?;
+            agent.total_collected = checked_reverse(agent.total_collected, receipt.amount)?;
+            agent.total_amil_fees = checked_reverse(agent.total_amil_fees, receipt.amil_fee)?;
+            // Commission already paid out on this payment is owed back by the agent
+            let unpaid = agent.commission_accrued.saturating_sub(agent.commission_paid);
+            agent.commission_accrued = checked_reverse(agent.commission_accrued, receipt.agent_commission)?;
+            let clawback = receipt.agent_commission.saturating_sub(unpaid);
+            if clawback > 0 {
+                emit!(AgentCommissionClawedBack {
+                    agent: agent.key(),
+                    receipt: receipt.key(),
+                    amount: clawback,
+                    timestamp,
+                });
+            }
+        }
+        if receipt.profile_credited {
+            let profile = ctx.accounts.profile.as_mut().ok_or(ZakaChainError::InvalidProfile)?;
+            profile.total_paid = checked_reverse(profile.total_paid, receipt.amount)?;
+        }
+        if receipt.fund != Pubkey::default() {
+            let fund = ctx.accounts.fund.as_mut().ok_or(ZakaChainError::InvalidFund)?;
+            fund.total_collected = checked_reverse(fund.total_collected, receipt.amount)?;
+            fund.total_amil_fees_collected = checked_reverse(fund.total_amil_fees_collected, receipt.amil_fee)?;
+        }
+        if receipt.invoice != Pubkey::default() {
+            // The installment counted towards the invoice before fees
+            let invoice = ctx.accounts.invoice.as_mut().ok_or(ZakaChainError::InvalidReceipt)?;
+            let paid = receipt.amount.checked_add(receipt.amil_fee).unwrap();
+            invoice.amount_paid = checked_reverse(invoice.amount_paid, paid)?;
+            if matches!(invoice.status, InvoiceStatus::Paid | InvoiceStatus::PartiallyPaid) {
+                invoice.status = if invoice.amount_paid == 0 {
+                    InvoiceStatus::Open
+                } else {
+                    InvoiceStatus::PartiallyPaid
+                };
+            }
+        }
+        if receipt.obligation != Pubkey::default() {
+            // Reopens a completed obligation; the haul it closed stays closed
+            let obligation = ctx.accounts.obligation.as_mut().ok_or(ZakaChainError::InvalidReceipt)?;
+            obligation.amount_paid = checked_reverse(obligation.amount_paid, receipt.amount)?;
+            obligation.completed_at = 0;
+        }
+        receipt.refunded = true;
+        emit!(ZakatRefunded {
+            receipt: receipt.key(),
+            payer: receipt.payer,
+            amount: receipt.amount,
+            amil_fee: receipt.amil_fee,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn distribute_to_mustahik(
+        ctx: Context<DistributeToMustahik>,
+        amount: u64,
+    ) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(
+            ctx.accounts.mustahik_account.status == MustahikStatus::Active,
+            ZakaChainError::MustahikInactive
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(
+            timestamp < ctx.accounts.mustahik_account.eligible_until,
+            ZakaChainError::EligibilityExpired
+        );
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        check_caps(
+            state,
+            &ctx.accounts.mustahik_account,
+            today,
+            amount,
+            amount,
+            ctx.accounts.amil.key(),
+            ctx.accounts.override_approver.as_ref(),
+            ctx.accounts.override_officer.as_deref(),
+            timestamp,
+        )?;
+        let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+        let signer = &[seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.program_token_account.to_account_info(),
+            to: ctx.accounts.mustahik_token_account.to_account_info(),
+            authority: state_info.clone(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            amount,
+        )?;
+        report_disbursement(
+            &ctx.accounts.mustahik_account,
+            ctx.accounts.core_program.as_ref().map(|program| program.to_account_info()),
+            ctx.accounts.registry_identity.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.registry_enrolment.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.registry_institution.as_ref().map(|account| account.to_account_info()),
+            state_info,
+            ctx.bumps.state,
+            amount,
+        )?;
+        record_distribution(state, &mut ctx.accounts.mustahik_account, today, timestamp, amount);
+        emit!(ZakatDistributed {
+            mustahik: ctx.accounts.mustahik.key(),
+            amount,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pays several registered mustahiks in one transaction. For every entry in
+    /// `amounts`, `remaining_accounts` holds the mustahik's PDA and their token
+    /// account, both writable. Any invalid leg fails the whole batch, as does
+    /// a leg over a cap: those go through `distribute_to_mustahik` with a
+    /// second approver. When the core program is passed, every entry is
+    /// followed by the mustahik's registry identity and enrolment, which are
+    /// required for enrolled mustahiks and ignored otherwise.
+    pub fn distribute_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DistributeBatch<'info>>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        require!(
+            !amounts.is_empty() && amounts.len() <= MAX_BATCH_DISTRIBUTIONS,
+            ZakaChainError::InvalidBatch
+        );
+        let stride = if ctx.accounts.core_program.is_some() { 4 } else { 2 };
+        require!(
+            ctx.remaining_accounts.len() == amounts.len() * stride,
+            ZakaChainError::InvalidBatch
+        );
+        let state_key = ctx.accounts.state.key();
+        let timestamp = Clock::get()?.unix_timestamp;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+        let signer = &[seeds];
+        let mut paid: Vec<Pubkey> = Vec::with_capacity(amounts.len());
+        for (amount, entry) in amounts.iter().zip(ctx.remaining_accounts.chunks(stride)) {
+            let (mustahik_info, token_info) = (&entry[0], &entry[1]);
+            require!(*amount > 0, ZakaChainError::InvalidAmount);
+            require!(mustahik_info.is_writable, ZakaChainError::InvalidMustahik);
+            let mut mustahik = Account::<Mustahik>::try_from(mustahik_info)?;
+            let expected = Pubkey::create_program_address(
+                &[b"mustahik", state_key.as_ref(), mustahik.wallet.as_ref(), &[mustahik.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| ZakaChainError::InvalidMustahik)?;
+            require!(mustahik_info.key() == expected, ZakaChainError::InvalidMustahik);
+            require!(!paid.contains(&mustahik.wallet), ZakaChainError::InvalidBatch);
+            require!(mustahik.status == MustahikStatus::Active, ZakaChainError::MustahikInactive);
+            require!(timestamp < mustahik.eligible_until, ZakaChainError::EligibilityExpired);
+            let token_account = Account::<TokenAccount>::try_from(token_info)?;
+            require!(
+                token_account.owner == mustahik.wallet && token_info.is_writable,
+                ZakaChainError::InvalidTokenAccount
+            );
+            check_caps(&ctx.accounts.state, &mustahik, today, *amount, *amount, ctx.accounts.amil.key(), None, None, timestamp)?;
+            let transfer_accounts = Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: token_info.clone(),
+                authority: ctx.accounts.state.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+                *amount,
+            )?;
+            report_disbursement(
+                &mustahik,
+                ctx.accounts.core_program.as_ref().map(|program| program.to_account_info()),
+                entry.get(2).cloned(),
+                entry.get(3).cloned(),
+                ctx.accounts.registry_institution.as_ref().map(|account| account.to_account_info()),
+                ctx.accounts.state.to_account_info(),
+                ctx.bumps.state,
+                *amount,
+            )?;
+            record_distribution(&mut ctx.accounts.state, &mut mustahik, today, timestamp, *amount);
+            mustahik.exit(&crate::ID)?;
+            paid.push(mustahik.wallet);
+            emit!(ZakatDistributed {
+                mustahik: mustahik.wallet,
+                amount: *amount,
+                timestamp,
+            });
+        }
+        Ok(())
+    }
+
+    /// Moves `amount` from the vault into the mustahik's claim escrow, to be
+    /// claimed by the mustahik before `expires_at`. While anything is still
+    /// escrowed, a top-up can extend the expiry but never shorten it. The
+    /// per-payment cap applies to everything still unclaimed plus `amount`;
+    /// `amount` is counted against the month and year caps right away, in the
+    /// month it is allocated, until it is reclaimed.
+    pub fn allocate_claim(ctx: Context<AllocateClaim>, amount: u64, expires_at: i64) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(amount > 0, ZakaChainError::InvalidAmount);
+        let mustahik = &ctx.accounts.mustahik_account;
+        require!(mustahik.status == MustahikStatus::Active, ZakaChainError::MustahikInactive);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp < mustahik.eligible_until, ZakaChainError::EligibilityExpired);
+        require!(expires_at > timestamp, ZakaChainError::InvalidClaimExpiry);
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let pending = ctx.accounts.claim.amount.checked_add(amount).unwrap();
+        check_caps(
+            state,
+            mustahik,
+            today,
+            pending,
+            amount,
+            ctx.accounts.amil.key(),
+            ctx.accounts.override_approver.as_ref(),
+            ctx.accounts.override_officer.as_deref(),
+            timestamp,
+        )?;
+        let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+        let signer = &[seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.program_token_account.to_account_info(),
+            to: ctx.accounts.claim_vault.to_account_info(),
+            authority: ctx.accounts.state.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            amount,
+        )?;
+        add_period_received(&mut ctx.accounts.mustahik_account, today, amount);
+        let claim = &mut ctx.accounts.claim;
+        if claim.amount > 0 {
+            claim.expires_at = claim.expires_at.max(expires_at);
+        } else {
+            claim.expires_at = expires_at;
+        }
+        claim.mustahik = ctx.accounts.mustahik_account.wallet;
+        claim.mustahik_account = ctx.accounts.mustahik_account.key();
+        claim.vault = ctx.accounts.claim_vault.key();
+        claim.amount = pending;
+        claim.total_allocated = claim.total_allocated.checked_add(amount).unwrap();
+        let (month_reserved, year_reserved) = claim.period_reserved(today);
+        claim.period_year = today.year;
+        claim.period_month = today.month;
+        claim.month_reserved = month_reserved.checked_add(amount).unwrap();
+        claim.year_reserved = year_reserved.checked_add(amount).unwrap();
+        claim.bump = ctx.bumps.claim;
+        emit!(ClaimAllocated {
+            mustahik: claim.mustahik,
+            claim: claim.key(),
+            amount,
+            expires_at: claim.expires_at,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// The mustahik collects their allocation into their own associated token
+    /// account, which is created if it does not exist yet. The month and year
+    /// totals already count it from when it was allocated.
+    pub fn claim_allocation(ctx: Context<ClaimAllocation>) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        let claim = &ctx.accounts.claim;
+        require!(claim.amount > 0, ZakaChainError::NothingToClaim);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp < claim.expires_at, ZakaChainError::ClaimExpired);
+        let mustahik = &ctx.accounts.mustahik_account;
+        require!(mustahik.status == MustahikStatus::Active, ZakaChainError::MustahikInactive);
+        require!(timestamp < mustahik.eligible_until, ZakaChainError::EligibilityExpired);
+        let amount = claim.amount;
+        let mustahik_account_key = mustahik.key();
+        let claim_seeds: &[&[u8]] = &[b"claim", mustahik_account_key.as_ref(), &[claim.bump]];
+        let signer = &[claim_seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.claim_vault.to_account_info(),
+            to: ctx.accounts.mustahik_token_account.to_account_info(),
+            authority: ctx.accounts.claim.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            amount,
+        )?;
+        report_disbursement(
+            &ctx.accounts.mustahik_account,
+            ctx.accounts.core_program.as_ref().map(|program| program.to_account_info()),
+            ctx.accounts.registry_identity.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.registry_enrolment.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.registry_institution.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.state.to_account_info(),
+            ctx.bumps.state,
+            amount,
+        )?;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        record_payment(
+            &mut ctx.accounts.state,
+            &mut ctx.accounts.mustahik_account,
+            today,
+            timestamp,
+            amount,
+        );
+        let claim = &mut ctx.accounts.claim;
+        claim.amount = 0;
+        claim.total_claimed = claim.total_claimed.checked_add(amount).unwrap();
+        claim.month_reserved = 0;
+        claim.year_reserved = 0;
+        emit!(AllocationClaimed {
+            mustahik: claim.mustahik,
+            claim: claim.key(),
+            amount,
+            timestamp,
+        });
+        emit!(ZakatDistributed {
+            mustahik: claim.mustahik,
+            amount,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Returns an expired, unclaimed allocation to the vault and takes it back
+    /// out of the mustahik's month and year totals, where those still count
+    /// it.
+    pub fn reclaim_allocation(ctx: Context<ReclaimAllocation>) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let claim = &ctx.accounts.claim;
+        require!(claim.amount > 0, ZakaChainError::NothingToClaim);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp >= claim.expires_at, ZakaChainError::ClaimNotExpired);
+        let amount = claim.amount;
+        let claim_seeds: &[&[u8]] = &[b"claim", claim.mustahik_account.as_ref(), &[claim.bump]];
+        let signer = &[claim_seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.claim_vault.to_account_info(),
+            to: ctx.accounts.program_token_account.to_account_info(),
+            authority: ctx.accounts.claim.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            amount,
+        )?;
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        let claim = &mut ctx.accounts.claim;
+        let (month_reserved, year_reserved) = claim.period_reserved(today);
+        let mustahik = &mut ctx.accounts.mustahik_account;
+        let (month_received, year_received) = mustahik.period_received(today);
+        mustahik.period_year = today.year;
+        mustahik.period_month = today.month;
+        mustahik.month_received = month_received.checked_sub(month_reserved).unwrap();
+        mustahik.year_received = year_received.checked_sub(year_reserved).unwrap();
+        claim.amount = 0;
+        claim.month_reserved = 0;
+        claim.year_reserved = 0;
+        emit!(AllocationReclaimed {
+            mustahik: claim.mustahik,
+            claim: claim.key(),
+            amount,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Publishes a distribution round: a Merkle root over
+    /// `keccak(index u32 LE || mustahik wallet || amount u64 LE)` leaves, with
+    /// `total_amount` moved from the vault into the round's own vault.
+    pub fn create_round(
+        ctx: Context<CreateRound>,
+        merkle_root: [u8; 32],
+        leaf_count: u32,
+        total_amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(
+            leaf_count > 0 && leaf_count <= MAX_ROUND_LEAVES,
+            ZakaChainError::InvalidRound
+        );
+        require!(total_amount > 0, ZakaChainError::InvalidAmount);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(expires_at > timestamp, ZakaChainError::InvalidClaimExpiry);
+        let seeds: &[&[u8]] = &[b"state", &[ctx.bumps.state]];
+        let signer = &[seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.program_token_account.to_account_info(),
+            to: ctx.accounts.round_vault.to_account_info(),
+            authority: ctx.accounts.state.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            total_amount,
+        )?;
+        let round = &mut ctx.accounts.round;
+        round.id = ctx.accounts.state.round_count;
+        round.merkle_root = merkle_root;
+        round.vault = ctx.accounts.round_vault.key();
+        round.total_amount = total_amount;
+        round.claimed_amount = 0;
+        round.leaf_count = leaf_count;
+        round.expires_at = expires_at;
+        round.closed = false;
+        round.bump = ctx.bumps.round;
+        round.claimed = vec![0; Round::bitmap_len(leaf_count)];
+        let state = &mut ctx.accounts.state;
+        state.round_count = state.round_count.checked_add(1).unwrap();
+        emit!(RoundCreated {
+            round: round.key(),
+            merkle_root,
+            leaf_count,
+            total_amount,
+            expires_at,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pays one leaf of a round to the mustahik's token account. Anyone can
+    /// submit the proof, so a crank can pay mustahiks who never sign.
+    pub fn claim_from_round(
+        ctx: Context<ClaimFromRound>,
+        index: u32,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        let round = &ctx.accounts.round;
+        require!(!round.closed, ZakaChainError::RoundClosed);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp < round.expires_at, ZakaChainError::ClaimExpired);
+        require!(index < round.leaf_count, ZakaChainError::InvalidProof);
+        require!(!round.is_claimed(index), ZakaChainError::AlreadyClaimed);
+        let wallet = ctx.accounts.mustahik_account.wallet;
+        let leaf = keccak::hashv(&[&index.to_le_bytes(), wallet.as_ref(), &amount.to_le_bytes()]).0;
+        require!(verify_merkle_proof(&proof, round.merkle_root, leaf), ZakaChainError::InvalidProof);
+        let mustahik = &ctx.accounts.mustahik_account;
+        require!(mustahik.status == MustahikStatus::Active, ZakaChainError::MustahikInactive);
+        require!(timestamp < mustahik.eligible_until, ZakaChainError::EligibilityExpired);
+        let today = hijri::from_unix_timestamp(timestamp, &ctx.accounts.hijri_calendar.month_starts);
+        // Rounds have no second approver, so caps apply strictly
+        check_caps(&ctx.accounts.state, mustahik, today, amount, amount, ctx.accounts.state.amil, None, None, timestamp)?;
+        let id_bytes = round.id.to_le_bytes();
+        let round_seeds: &[&[u8]] = &[b"round", id_bytes.as_ref(), &[round.bump]];
+        let signer = &[round_seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.round_vault.to_account_info(),
+            to: ctx.accounts.mustahik_token_account.to_account_info(),
+            authority: ctx.accounts.round.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            amount,
+        )?;
+        report_disbursement(
+            &ctx.accounts.mustahik_account,
+            ctx.accounts.core_program.as_ref().map(|program| program.to_account_info()),
+            ctx.accounts.registry_identity.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.registry_enrolment.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.registry_institution.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.state.to_account_info(),
+            ctx.bumps.state,
+            amount,
+        )?;
+        record_distribution(
+            &mut ctx.accounts.state,
+            &mut ctx.accounts.mustahik_account,
+            today,
+            timestamp,
+            amount,
+        );
+        let round = &mut ctx.accounts.round;
+        round.set_claimed(index);
+        round.claimed_amount = round.claimed_amount.checked_add(amount).unwrap();
+        emit!(RoundClaimed {
+            round: round.key(),
+            mustahik: wallet,
+            index,
+            amount,
+            timestamp,
+        });
+        emit!(ZakatDistributed {
+            mustahik: wallet,
+            amount,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    /// Closes an expired round and returns what was not claimed to the vault.
+    pub fn close_round(ctx: Context<CloseRound>) -> Result<()> {
+        require!(ctx.accounts.state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == ctx.accounts.state.amil, ZakaChainError::Unauthorized);
+        let round = &ctx.accounts.round;
+        require!(!round.closed, ZakaChainError::RoundClosed);
+        let timestamp = Clock::get()?.unix_timestamp;
+        require!(timestamp >= round.expires_at, ZakaChainError::ClaimNotExpired);
+        let leftover = ctx.accounts.round_vault.amount;
+        if leftover > 0 {
+            let id_bytes = round.id.to_le_bytes();
+            let round_seeds: &[&[u8]] = &[b"round", id_bytes.as_ref(), &[round.bump]];
+            let signer = &[round_seeds];
+            let transfer_accounts = Transfer {
+                from: ctx.accounts.round_vault.to_account_info(),
+                to: ctx.accounts.program_token_account.to_account_info(),
+                authority: ctx.accounts.round.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+                leftover,
+            )?;
+        }
+        let round = &mut ctx.accounts.round;
+        round.closed = true;
+        emit!(RoundClosed {
+            round: round.key(),
+            claimed_amount: round.claimed_amount,
+            leftover,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_amil_fees(
+        ctx: Context<WithdrawAmilFees>,
+        amount: u64,
+    ) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        let state = &mut ctx.accounts.state;
+        require!(state.is_initialized, ZakaChainError::NotInitialized);
+        require!(ctx.accounts.amil.key() == state.amil, ZakaChainError::Unauthorized);
+        require!(amount <= state.total_amil_fees_collected, ZakaChainError::InsufficientFunds);
+        let seeds: &[&[u8]] = &[b"state"];
+        let signer = &[seeds];
+        let transfer_accounts = Transfer {
+            from: ctx.accounts.program_token_account.to_account_info(),
+            to: ctx.accounts.amil_token_account.to_account_info(),
+            authority: state_info,
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), transfer_accounts, signer),
+            amount,
+        )?;
+        state.total_amil_fees_collected = state.total_amil_fees_collected.checked_sub(amount).unwrap();
+        state.last_withdrawal_timestamp = Clock::get()?.unix_timestamp;
+        state.manual_withdrawal_count = state.manual_withdrawal_count.checked_add(1).unwrap();
+        emit!(AmilFeesWithdrawn {
+            amil: ctx.accounts.amil.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+}
+
+fn calculate_amil_fee(amount: u64, fee_percentage: u8) -> u64 {
+    (amount as u128)
+        .checked_mul(fee_percentage as u128)
+        .unwrap()
+        .checked_div(1000)
+        .unwrap() as u64
+}
+
+/// Starts a new Hijri month bucket when the month has changed, and a new
+/// Hijri year bucket when the year has, emitting the totals of the buckets
+/// being closed.
+fn roll_hijri_period(state: &mut ZakaChainState, today: HijriDate, timestamp: i64) {
+    if state.period_year == today.year && state.period_month == today.month {
+        return;
+    }
+    if state.period_year != 0 {
+        emit!(HijriPeriodClosed {
+            year: state.period_year,
+            month: state.period_month,
+            zakat_collected: state.period_zakat_collected,
+            amil_fees_collected: state.period_amil_fees_collected,
+            zakat_distributed: state.period_zakat_distributed,
+            timestamp,
+        });
+    }
+    if state.period_year != today.year {
+        if state.period_year != 0 {
+            emit!(HijriYearClosed {
+                year: state.period_year,
+                zakat_collected: state.year_zakat_collected,
+                amil_fees_collected: state.year_amil_fees_collected,
+                zakat_distributed: state.year_zakat_distributed,
+                timestamp,
+            });
+        }
+        state.year_zakat_collected = 0;
+        state.year_amil_fees_collected = 0;
+        state.year_zakat_distributed = 0;
+    }
+    state.period_year = today.year;
+    state.period_month = today.month;
+    state.period_zakat_collected = 0;
+    state.period_amil_fees_collected = 0;
+    state.period_zakat_distributed = 0;
+}
+
+fn record_collection(state: &mut ZakaChainState, today: HijriDate, timestamp: i64, net_amount: u64, amil_fee: u64) {
+    roll_hijri_period(state, today, timestamp);
+    state.total_zakat_collected = state.total_zakat_collected.checked_add(net_amount).unwrap();
+    state.total_amil_fees_collected = state.total_amil_fees_collected.checked_add(amil_fee).unwrap();
+    state.period_zakat_collected = state.period_zakat_collected.checked_add(net_amount).unwrap();
+    state.period_amil_fees_collected = state.period_amil_fees_collected.checked_add(amil_fee).unwrap();
+    state.year_zakat_collected = state.year_zakat_collected.checked_add(net_amount).unwrap();
+    state.year_amil_fees_collected = state.year_amil_fees_collected.checked_add(amil_fee).unwrap();
+}
+
+/// Creates a program-owned PDA of `space` bytes, paid by `payer`. An address
+/// that already holds lamports is topped up to rent exemption, allocated and
+/// assigned instead, so sending lamports to a predictable address first
+/// cannot block its creation.
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let lamports = account.lamports();
+    if lamports == 0 {
+        return system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+                &[signer_seeds],
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        );
+    }
+    if lamports < rent {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            rent - lamports,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Allocate {
+                account_to_allocate: account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Assign {
+                account_to_assign: account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        &crate::ID,
+    )
+}
+
+/// Moves the sponsor's match for a donation from the pool vault into the
+/// program vault, within the pool's caps and what is left in the vault.
+/// Returns the matched amount, which is zero once the pool has ended.
+fn match_donation<'info>(
+    pool: &mut Account<'info, MatchingPool>,
+    pool_vault: &Account<'info, TokenAccount>,
+    donor_match: &mut DonorMatch,
+    program_token_account: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    donation: u64,
+    timestamp: i64,
+) -> Result<u64> {
+    if timestamp >= pool.end_timestamp {
+        return Ok(0);
+    }
+    let matched = ((donation as u128)
+        .checked_mul(pool.match_ratio_bps as u128)
+        .unwrap()
+        .checked_div(10_000)
+        .unwrap() as u64)
+        .min(pool.per_donor_cap.saturating_sub(donor_match.matched))
+        .min(pool.total_cap.saturating_sub(pool.total_matched))
+        .min(pool_vault.amount);
+    if matched == 0 {
+        return Ok(0);
+    }
+    let pool_seeds: &[&[u8]] = &[
+        b"matching_pool",
+        pool.sponsor.as_ref(),
+        pool.fund.as_ref(),
+        &[pool.bump],
+    ];
+    let signer = &[pool_seeds];
+    let transfer_accounts = Transfer {
+        from: pool_vault.to_account_info(),
+        to: program_token_account,
+        authority: pool.to_account_info(),
+    };
+    token::transfer(
+        CpiContext::new_with_signer(token_program, transfer_accounts, signer),
+        matched,
+    )?;
+    donor_match.matched = donor_match.matched.checked_add(matched).unwrap();
+    pool.total_matched = pool.total_matched.checked_add(matched).unwrap();
+    Ok(matched)
+}
+
+/// Checks the fund's Hijri window and the payer's fee limit, then adds the
+/// payment to the fund's totals. Returns the net amount and the amil fee.
+fn charge_fund(fund: &mut Fund, today: &HijriDate, amount: u64, max_fee_percentage: u8) -> Result<(u64, u64)> {
+    require!(fund.window.is_open(today), ZakaChainError::OutsideCollectionWindow);
+    require!(
+        fund.amil_fee_percentage <= max_fee_percentage,
+        ZakaChainError::FeeExceedsMaximum
+    );
+    let amil_fee = calculate_amil_fee(amount, fund.amil_fee_percentage);
+    let net_amount = amount.checked_sub(amil_fee).unwrap();
+    fund.total_collected = fund.total_collected.checked_add(net_amount).unwrap();
+    fund.total_amil_fees_collected = fund.total_amil_fees_collected.checked_add(amil_fee).unwrap();
+    Ok((net_amount, amil_fee))
+}
+
+/// A receipt for leg `index` of the payer's payment, numbered from
+/// `state.receipt_count`. It is issued to the payer as muzakki for the general
+/// pool; callers fill in the fund, agent, integration, invoice or obligation
+/// where they apply.
+#[allow(clippy::too_many_arguments)]
+fn issue_receipt(
+    state: &mut ZakaChainState,
+    payer: Pubkey,
+    payment_id: [u8; 16],
+    index: u8,
+    net_amount: u64,
+    amil_fee: u64,
+    timestamp: i64,
+    today: HijriDate,
+    bump: u8,
+) -> Receipt {
+    let id = state.receipt_count;
+    state.receipt_count = state.receipt_count.checked_add(1).unwrap();
+    Receipt {
+        id,
+        payer,
+        amount: net_amount,
+        amil_fee,
+        timestamp,
+        refunded: false,
+        agent: Pubkey::default(),
+        agent_commission: 0,
+        muzakki: payer,
+        integration: Pubkey::default(),
+        payment_id,
+        index,
+        fund: Pubkey::default(),
+        invoice: Pubkey::default(),
+        obligation: Pubkey::default(),
+        profile_credited: false,
+        paid_year: today.year,
+        paid_month: today.month,
+        bump,
+    }
+}
+
+/// The idempotency marker of a payment, pointing at its (first) receipt.
+fn mark_payment(payer: Pubkey, payment_id: [u8; 16], receipt: Pubkey, timestamp: i64, bump: u8) -> PaymentMarker {
+    PaymentMarker {
+        payer,
+        payment_id,
+        receipt,
+        timestamp,
+        bump,
+    }
+}
+
+/// Takes a refunded payment out of the lifetime totals, and out of the current
+/// Hijri month's totals when it was paid in that month.
+fn reverse_collection(
+    state: &mut ZakaChainState,
+    paid_year: u16,
+    paid_month: u8,
+    net_amount: u64,
+    amil_fee: u64,
+) -> Result<()> {
+    state.total_zakat_collected = checked_reverse(state.total_zakat_collected, net_amount)?;
+    state.total_amil_fees_collected = checked_reverse(state.total_amil_fees_collected, amil_fee)?;
+    if state.period_year == paid_year && state.period_month == paid_month {
+        state.period_zakat_collected = checked_reverse(state.period_zakat_collected, net_amount)?;
+        state.period_amil_fees_collected = checked_reverse(state.period_amil_fees_collected, amil_fee)?;
+    }
+    if state.period_year == paid_year {
+        state.year_zakat_collected = checked_reverse(state.year_zakat_collected, net_amount)?;
+        state.year_amil_fees_collected = checked_reverse(state.year_amil_fees_collected, amil_fee)?;
+    }
+    Ok(())
+}
+
+/// Subtracts a refunded amount from a running total. Totals can already be
+/// lower than the refund, e.g. after the amil withdrew fees.
+fn checked_reverse(total: u64, amount: u64) -> Result<u64> {
+    total
+        .checked_sub(amount)
+        .ok_or_else(|| error!(ZakaChainError::RefundExceedsTotals))
+}
+
+/// Sorted-pair keccak Merkle proof, as produced by common Merkle tree libraries.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let mut computed = leaf;
+    for node in proof {
+        computed = if computed <= *node {
+            keccak::hashv(&[&computed, node]).0
+        } else {
+            keccak::hashv(&[node, &computed]).0
+        };
+    }
+    computed == root
+}
+
+/// Adds a distribution to an enrolled mustahik's totals in the core identity
+/// registry, signing as the state PDA. Every payment to an enrolled mustahik
+/// must be reported, so the registry accounts are required for them.
+#[allow(clippy::too_many_arguments)]
+fn report_disbursement<'info>(
+    mustahik: &Mustahik,
+    core_program: Option<AccountInfo<'info>>,
+    identity: Option<AccountInfo<'info>>,
+    enrolment: Option<AccountInfo<'info>>,
+    institution: Option<AccountInfo<'info>>,
+    state: AccountInfo<'info>,
+    state_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    if mustahik.enrolment == Pubkey::default() {
+        return Ok(());
+    }
+    let (Some(core_program), Some(identity), Some(enrolment), Some(institution)) =
+        (core_program, identity, enrolment, institution)
+    else {
+        return err!(ZakaChainError::InvalidRegistryAccounts);
+    };
+    require!(enrolment.key() == mustahik.enrolment, ZakaChainError::InvalidRegistryAccounts);
+    let seeds: &[&[u8]] = &[b"state", &[state_bump]];
+    let signer = &[seeds];
+    let report_accounts = zakachain_core::cpi::accounts::ReportDisbursement {
+        identity,
+        enrolment,
+        institution,
+        authority: state,
+    };
+    zakachain_core::cpi::report_disbursement(
+        CpiContext::new_with_signer(core_program, report_accounts, signer),
+        amount,
+    )
+}
+
+/// Fails when a payment of `payment`, of which `amount` is not yet counted in
+/// the mustahik's totals, would take the mustahik over their own or their
+/// asnaf's caps, unless an approver officer other than the amil co-signs the
+/// override.
+/// The amil registers officers, so this is a second key rather than a second
+/// party unless the approver's key is held by someone the amil does not control.
+#[allow(clippy::too_many_arguments)]
+fn check_caps(
+    state: &ZakaChainState,
+    mustahik: &Mustahik,
+    today: HijriDate,
+    payment: u64,
+    amount: u64,
+    amil: Pubkey,
+    override_approver: Option<&Signer>,
+    override_officer: Option<&Officer>,
+    timestamp: i64,
+) -> Result<()> {
+    let asnaf_caps = &state.asnaf_caps[mustahik.asnaf as usize];
+    if !mustahik.caps.is_exceeded(mustahik, today, payment, amount)
+        && !asnaf_caps.is_exceeded(mustahik, today, payment, amount)
+    {
+        return Ok(());
+    }
+    let approver = override_approver.ok_or(ZakaChainError::CapExceeded)?;
+    let officer = override_officer.ok_or(ZakaChainError::CapExceeded)?;
+    require!(
+        officer.wallet == approver.key()
+            && officer.role == OfficerRole::Approver
+            && approver.key() != amil,
+        ZakaChainError::InvalidOverride
+    );
+    emit!(CapOverridden {
+        mustahik: mustahik.wallet,
+        approver: approver.key(),
+        amount,
+        timestamp,
+    });
+    Ok(())
+}
+
+/// Adds a disbursement to the institution's totals and to the mustahik's
+/// lifetime, Hijri month and Hijri year totals.
+fn record_distribution(
+    state: &mut ZakaChainState,
+    mustahik: &mut Mustahik,
+    today: HijriDate,
+    timestamp: i64,
+    amount: u64,
+) {
+    add_period_received(mustahik, today, amount);
+    record_payment(state, mustahik, today, timestamp, amount);
+}
+
+/// Adds `amount` to the mustahik's Hijri month and year totals, which the
+/// caps are checked against.
+fn add_period_received(mustahik: &mut Mustahik, today: HijriDate, amount: u64) {
+    let (month_received, year_received) = mustahik.period_received(today);
+    mustahik.period_year = today.year;
+    mustahik.period_month = today.month;
+    mustahik.month_received = month_received.checked_add(amount).unwrap();
+    mustahik.year_received = year_received.checked_add(amount).unwrap();
+}
+
+/// Adds a disbursement to the institution's totals and to the mustahik's
+/// lifetime totals.
+fn record_payment(
+    state: &mut ZakaChainState,
+    mustahik: &mut Mustahik,
+    today: HijriDate,
+    timestamp: i64,
+    amount: u64,
+) {
+    roll_hijri_period(state, today, timestamp);
+    state.total_zakat_distributed = state.total_zakat_distributed.checked_add(amount).unwrap();
+    state.period_zakat_distributed = state.period_zakat_distributed.checked_add(amount).unwrap();
+    state.year_zakat_distributed = state.year_zakat_distributed.checked_add(amount).unwrap();
+    mustahik.total_received = mustahik.total_received.checked_add(amount).unwrap();
+    mustahik.payment_count = mustahik.payment_count.checked_add(1).unwrap();
+    mustahik.last_payment_at = timestamp;
+}
+
+/// Start of the day `ELIGIBILITY_PERIOD_YEARS` Hijri years after `timestamp`.
+fn eligibility_end(timestamp: i64, month_starts: &[MonthStart]) -> i64 {
+    let today = hijri::from_unix_timestamp(timestamp, month_starts);
+    hijri::to_unix_timestamp(hijri::add_years(today, ELIGIBILITY_PERIOD_YEARS), month_starts)
+}
+
+/// The haul anniversary: the same Hijri date one year after the haul started.
+fn haul_due_at(profile: &MuzakkiProfile, month_starts: &[MonthStart]) -> i64 {
+    hijri::to_unix_timestamp(hijri::add_years(profile.haul_start_date(), 1), month_starts)
+}
+
+/// Records a completed obligation against the muzakki's haul. Only the
+/// obligation for the Hijri year the current haul falls due in closes it and
+/// starts the next haul; paying off an earlier or later year leaves it open.
+fn complete_haul(profile: &mut MuzakkiProfile, haul_year: u16, month_starts: &[MonthStart], timestamp: i64) {
+    let due_on = hijri::add_years(profile.haul_start_date(), 1);
+    if due_on.year != haul_year {
+        return;
+    }
+    profile.last_completed_at = timestamp;
+    profile.completed_haul_year = haul_year;
+    profile.set_haul_start(hijri::to_unix_timestamp(due_on, month_starts), due_on);
+}
+
+fn calculate_agent_commission(amil_fee: u64, commission_bps: u16) -> u64 {
+    (amil_fee as u128)
+        .checked_mul(commission_bps as u128)
+        .unwrap()
+        .checked_div(10_000)
+        .unwrap() as u64
+}
+
+/// Moves a collected payment: the net amount into the program vault and
+/// the amil fee into the amil's token account.
+fn transfer_collection<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    program_token_account: AccountInfo<'info>,
+    amil_token_account: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    net_amount: u64,
+    amil_fee: u64,
+) -> Result<()> {
+    let transfer_accounts = Transfer {
+        from: from.clone(),
+        to: program_token_account,
+        authority: authority.clone(),
+    };
+    token::transfer(
+        CpiContext::new(token_program.clone(), transfer_accounts),
+        net_amount,
+    )?;
+    if amil_fee > 0 {
+        let transfer_accounts = Transfer {
+            from,
+            to: amil_token_account,
+            authority,
+        };
+        token::transfer(CpiContext::new(token_program, transfer_accounts), amil_fee)?;
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + ZakaChainState::LEN,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateState<'info> {
+    /// CHECK: read by hand in `migrate_state`; the legacy layout does not
+    /// deserialize as `ZakaChainState`
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + LegacyMustahiks::LEN,
+        seeds = [b"legacy_mustahiks"],
+        bump
+    )]
+    pub legacy_mustahiks: Account<'info, LegacyMustahiks>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct MigrateLegacyMustahik<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(mut, seeds = [b"legacy_mustahiks"], bump = legacy_mustahiks.bump)]
+    pub legacy_mustahiks: Account<'info, LegacyMustahiks>,
+    #[account(
+        init_if_needed,
+        payer = amil,
+        space = 8 + Mustahik::LEN,
+        seeds = [b"mustahik", state.key().as_ref(), wallet.as_ref()],
+        bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAuthority<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeePercentage<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRefundWindow<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMaxRelayerFee<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNisab<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAsnafCaps<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrice<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = amil,
+        space = 8 + PriceFeed::LEN,
+        seeds = [b"price", mint.key().as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CalculateZakat<'info> {
+    pub state: Account<'info, ZakaChainState>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct RegisterAgent<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + CollectionAgent::LEN,
+        seeds = [b"agent", wallet.as_ref()],
+        bump
+    )]
+    pub agent: Account<'info, CollectionAgent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAgent<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(mut, seeds = [b"agent", agent.wallet.as_ref()], bump = agent.bump)]
+    pub agent: Account<'info, CollectionAgent>,
+}
+
+#[derive(Accounts)]
+pub struct PayAgentCommission<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(mut, seeds = [b"agent", agent.wallet.as_ref()], bump = agent.bump)]
+    pub agent: Account<'info, CollectionAgent>,
+    #[account(
+        mut,
+        constraint = amil_token_account.owner == amil.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = agent_token_account.owner == agent.wallet @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub agent_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RepayAgentCommission<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    pub wallet: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"agent", wallet.key().as_ref()],
+        bump = agent.bump
+    )]
+    pub agent: Account<'info, CollectionAgent>,
+    #[account(
+        mut,
+        constraint = agent_token_account.owner == wallet.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub agent_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(fund_id: u8)]
+pub struct CreateFund<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + Fund::LEN,
+        seeds = [b"fund", [fund_id].as_ref()],
+        bump
+    )]
+    pub fund: Account<'info, Fund>,
+    pub mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFund<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(mut, seeds = [b"fund", [fund.fund_id].as_ref()], bump = fund.bump)]
+    pub fund: Account<'info, Fund>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct RegisterOfficer<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + Officer::LEN,
+        seeds = [b"officer", wallet.as_ref()],
+        bump
+    )]
+    pub officer: Account<'info, Officer>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveOfficer<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        close = amil,
+        seeds = [b"officer", officer.wallet.as_ref()],
+        bump = officer.bump
+    )]
+    pub officer: Account<'info, Officer>,
+}
+
+#[derive(Accounts)]
+#[instruction(mustahik_address: Pubkey)]
+pub struct SubmitApplication<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub applicant: Signer<'info>,
+    /// Required when a field officer applies on the candidate's behalf.
+    #[account(seeds = [b"officer", applicant.key().as_ref()], bump = officer.bump)]
+    pub officer: Option<Account<'info, Officer>>,
+    #[account(
+        init_if_needed,
+        payer = applicant,
+        space = 8 + Mustahik::LEN,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik_address.as_ref()],
+        bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyIdentity<'info> {
+    pub mustahik: Account<'info, Mustahik>,
+}
+
+#[derive(Accounts)]
+pub struct RecordSurvey<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub surveyor: Signer<'info>,
+    #[account(seeds = [b"officer", surveyor.key().as_ref()], bump = officer.bump)]
+    pub officer: Account<'info, Officer>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik.wallet.as_ref()],
+        bump = mustahik.bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+}
+
+#[derive(Accounts)]
+pub struct EmitEligibilityExpiring<'info> {
+    pub state: Account<'info, ZakaChainState>,
+}
+
+#[derive(Accounts)]
+pub struct ReviewApplication<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub approver: Signer<'info>,
+    #[account(seeds = [b"officer", approver.key().as_ref()], bump = officer.bump)]
+    pub officer: Account<'info, Officer>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik.wallet.as_ref()],
+        bump = mustahik.bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// Core identity registry, to enrol an approved mustahik
+    pub core_program: Option<Program<'info, ZakachainCore>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_identity: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_enrolment: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    pub registry_institution: Option<UncheckedAccount<'info>>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[derive(Accounts)]
+pub struct ReverifyMustahik<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub surveyor: Signer<'info>,
+    #[account(seeds = [b"officer", surveyor.key().as_ref()], bump = officer.bump)]
+    pub officer: Account<'info, Officer>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik.wallet.as_ref()],
+        bump = mustahik.bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMustahik<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik.wallet.as_ref()],
+        bump = mustahik.bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+}
+
+#[derive(Accounts)]
+#[instruction(mustahik_address: Pubkey)]
+pub struct RemoveMustahik<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        close = amil,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik_address.as_ref()],
+        bump = mustahik.bump
+    )]
+    pub mustahik: Account<'info, Mustahik>,
+    /// Core identity registry, required once the mustahik is enrolled
+    pub core_program: Option<Program<'info, ZakachainCore>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_identity: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_enrolment: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    pub registry_institution: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, max_fee_percentage: u8, expected_net_amount: Option<u64>, payment_id: [u8; 16])]
+pub struct CollectZakat<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub payer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = state)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = program_token_account.mint,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Receipt::LEN,
+        seeds = [b"receipt", payer.key().as_ref(), payment_id.as_ref(), [0u8].as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, Receipt>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", payer.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    #[account(mut, seeds = [b"agent", agent.wallet.as_ref()], bump = agent.bump)]
+    pub agent: Option<Account<'info, CollectionAgent>>,
+    #[account(mut, seeds = [b"fund", [fund.fund_id].as_ref()], bump = fund.bump)]
+    pub fund: Option<Box<Account<'info, Fund>>>,
+    #[account(
+        mut,
+        seeds = [b"obligation", payer.key().as_ref(), obligation.fund.as_ref(), obligation.haul_year.to_le_bytes().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Option<Box<Account<'info, Obligation>>>,
+    #[account(mut, seeds = [b"muzakki", payer.key().as_ref()], bump = profile.bump)]
+    pub profile: Option<Box<Account<'info, MuzakkiProfile>>>,
+    #[account(
+        mut,
+        seeds = [b"matching_pool", pool.sponsor.as_ref(), pool.fund.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Option<Box<Account<'info, MatchingPool>>>,
+    #[account(mut)]
+    pub pool_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + DonorMatch::LEN,
+        seeds = [b"donor_match", pool.as_ref().map(|pool| pool.key()).unwrap_or_default().as_ref(), payer.key().as_ref()],
+        bump
+    )]
+    pub donor_match: Option<Box<Account<'info, DonorMatch>>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amounts: Vec<u64>, max_fee_percentage: u8, payment_id: [u8; 16])]
+pub struct CollectPayroll<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub employer: Signer<'info>,
+    #[account(mut)]
+    pub employer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = state)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = program_token_account.mint,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = employer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", employer.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    amount: u64,
+    relayer_fee: u64,
+    max_fee_percentage: u8,
+    expected_net_amount: Option<u64>,
+    payment_id: [u8; 16]
+)]
+pub struct CollectZakatRelayed<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+    pub donor: Signer<'info>,
+    #[account(
+        mut,
+        constraint = donor_token_account.owner == donor.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub donor_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = relayer_token_account.owner == relayer.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub relayer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = state)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = program_token_account.mint,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = relayer,
+        space = 8 + Receipt::LEN,
+        seeds = [b"receipt", donor.key().as_ref(), payment_id.as_ref(), [0u8].as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, Receipt>,
+    #[account(
+        init,
+        payer = relayer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", donor.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(authority: Pubkey)]
+pub struct RegisterIntegration<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + ProtocolIntegration::LEN,
+        seeds = [b"integration", authority.as_ref()],
+        bump
+    )]
+    pub integration: Account<'info, ProtocolIntegration>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateIntegration<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"integration", integration.authority.as_ref()],
+        bump = integration.bump
+    )]
+    pub integration: Account<'info, ProtocolIntegration>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, max_fee_percentage: u8, payment_id: [u8; 16])]
+pub struct CollectZakatFor<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(
+        mut,
+        seeds = [b"integration", protocol_authority.key().as_ref()],
+        bump = integration.bump
+    )]
+    pub integration: Account<'info, ProtocolIntegration>,
+    /// The integrating program's PDA, signing through `invoke_signed`.
+    pub protocol_authority: Signer<'info>,
+    /// CHECK: the muzakki; only used as the owner of `user_token_account`.
+    pub user: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ ZakaChainError::InvalidTokenAccount,
+        constraint = user_token_account.delegate == Some(protocol_authority.key()).into()
+            @ ZakaChainError::InvalidDelegate
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    /// The state PDA's associated token account, as derived by the interface crate
+    #[account(
+        mut,
+        associated_token::mint = user_token_account.mint,
+        associated_token::authority = state
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = user_token_account.mint,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Receipt::LEN,
+        seeds = [b"receipt", user.key().as_ref(), payment_id.as_ref(), [0u8].as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, Receipt>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", user.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// CHECK: the instructions sysvar, read to find the calling program
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(parts: Vec<FundPart>, max_fee_percentage: u8, expected_net_amount: Option<u64>, payment_id: [u8; 16])]
+pub struct CollectSplit<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub payer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = state)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = program_token_account.mint,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", payer.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateInvoice<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(seeds = [b"fund", [fund.fund_id].as_ref()], bump = fund.bump)]
+    pub fund: Account<'info, Fund>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + Invoice::LEN,
+        seeds = [b"invoice", state.invoice_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, max_fee_percentage: u8, payment_id: [u8; 16])]
+pub struct PayInvoice<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
+        bump = invoice.bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+    #[account(mut, address = invoice.fund @ ZakaChainError::InvalidFund)]
+    pub fund: Account<'info, Fund>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub payer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = fund.mint, token::authority = state)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = fund.mint,
+        constraint = amil_token_account.owner == state.amil @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Receipt::LEN,
+        seeds = [b"receipt", payer.key().as_ref(), payment_id.as_ref(), [0u8].as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, Receipt>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", payer.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateInvoice<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
+        bump = invoice.bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterMuzakki<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + MuzakkiProfile::LEN,
+        seeds = [b"muzakki", owner.key().as_ref()],
+        bump
+    )]
+    pub profile: Account<'info, MuzakkiProfile>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMuzakki<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"muzakki", owner.key().as_ref()],
+        bump = profile.bump
+    )]
+    pub profile: Account<'info, MuzakkiProfile>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+}
+
+#[derive(Accounts)]
+pub struct EmitZakatDue<'info> {
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+}
+
+#[derive(Accounts)]
+#[instruction(haul_year: u16)]
+pub struct RegisterObligation<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub muzakki: Signer<'info>,
+    #[account(seeds = [b"fund", [fund.fund_id].as_ref()], bump = fund.bump)]
+    pub fund: Account<'info, Fund>,
+    #[account(
+        init,
+        payer = muzakki,
+        space = 8 + Obligation::LEN,
+        seeds = [b"obligation", muzakki.key().as_ref(), fund.key().as_ref(), haul_year.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetObligationRemaining<'info> {
+    pub obligation: Account<'info, Obligation>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMatchingPool<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+    #[account(seeds = [b"fund", [fund.fund_id].as_ref()], bump = fund.bump)]
+    pub fund: Account<'info, Fund>,
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + MatchingPool::LEN,
+        seeds = [b"matching_pool", sponsor.key().as_ref(), fund.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, MatchingPool>,
+    #[account(address = fund.mint @ ZakaChainError::InvalidTokenAccount)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = sponsor,
+        seeds = [b"matching_vault", pool.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = pool
+    )]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub sponsor_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMatchingPool<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+    #[account(
+        mut,
+        close = sponsor,
+        has_one = sponsor @ ZakaChainError::Unauthorized,
+        seeds = [b"matching_pool", pool.sponsor.as_ref(), pool.fund.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, MatchingPool>,
+    #[account(mut, address = pool.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = sponsor_token_account.owner == sponsor.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub sponsor_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateCampaign<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + Campaign::LEN,
+        seeds = [b"campaign", state.campaign_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = amil,
+        seeds = [b"campaign_vault", campaign.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = campaign
+    )]
+    pub campaign_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, payment_id: [u8; 16])]
+pub struct DonateToCampaign<'info> {
+    #[account(
+        mut,
+        seeds = [b"campaign", campaign.id.to_le_bytes().as_ref()],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut, address = campaign.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub campaign_vault: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = donor,
+        space = 8 + Contribution::LEN,
+        seeds = [b"contribution", campaign.key().as_ref(), donor.key().as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(
+        init,
+        payer = donor,
+        space = 8 + PaymentMarker::LEN,
+        seeds = [b"payment", donor.key().as_ref(), payment_id.as_ref()],
+        bump
+    )]
+    pub payment: Account<'info, PaymentMarker>,
+    #[account(mut)]
+    pub donor: Signer<'info>,
+    #[account(mut)]
+    pub donor_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseCampaign<'info> {
+    #[account(
+        mut,
+        seeds = [b"campaign", campaign.id.to_le_bytes().as_ref()],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut, address = campaign.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub campaign_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RefundContribution<'info> {
+    #[account(
+        mut,
+        seeds = [b"campaign", campaign.id.to_le_bytes().as_ref()],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut, address = campaign.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub campaign_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"contribution", campaign.key().as_ref(), donor.key().as_ref()],
+        bump = contribution.bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    pub donor: Signer<'info>,
+    #[account(
+        mut,
+        constraint = donor_token_account.owner == donor.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub donor_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RefundZakat<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"receipt", receipt.payer.as_ref(), receipt.payment_id.as_ref(), [receipt.index].as_ref()],
+        bump = receipt.bump
+    )]
+    pub receipt: Account<'info, Receipt>,
+    #[account(
+        mut,
+        constraint = payer_token_account.owner == receipt.payer @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub payer_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = amil_token_account.owner == amil.key() @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub amil_token_account: Account<'info, TokenAccount>,
+    #[account(mut, address = receipt.agent @ ZakaChainError::InvalidAgent)]
+    pub agent: Option<Account<'info, CollectionAgent>>,
+    #[account(
+        mut,
+        constraint = profile.owner == receipt.muzakki @ ZakaChainError::InvalidProfile
+    )]
+    pub profile: Option<Account<'info, MuzakkiProfile>>,
+    #[account(mut, address = receipt.fund @ ZakaChainError::InvalidFund)]
+    pub fund: Option<Account<'info, Fund>>,
+    #[account(mut, address = receipt.invoice @ ZakaChainError::InvalidReceipt)]
+    pub invoice: Option<Account<'info, Invoice>>,
+    #[account(mut, address = receipt.obligation @ ZakaChainError::InvalidReceipt)]
+    pub obligation: Option<Box<Account<'info, Obligation>>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeToMustahik<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    /// CHECK: This is the mustahik's wallet address
+    pub mustahik: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik.key().as_ref()],
+        bump = mustahik_account.bump
+    )]
+    pub mustahik_account: Account<'info, Mustahik>,
+    #[account(mut)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = program_token_account.mint,
+        constraint = mustahik_token_account.owner == mustahik_account.wallet @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub mustahik_token_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// Second approver, required only when the payment exceeds a cap.
+    pub override_approver: Option<Signer<'info>>,
+    #[account(
+        seeds = [b"officer", override_approver.as_ref().map(|approver| approver.key()).unwrap_or_default().as_ref()],
+        bump = override_officer.bump
+    )]
+    pub override_officer: Option<Account<'info, Officer>>,
+    /// Core identity registry, required once the mustahik is enrolled
+    pub core_program: Option<Program<'info, ZakachainCore>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_identity: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_enrolment: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    pub registry_institution: Option<UncheckedAccount<'info>>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeBatch<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(mut)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// Core identity registry; each entry's identity and enrolment follow it
+    /// in `remaining_accounts`
+    pub core_program: Option<Program<'info, ZakachainCore>>,
+    /// CHECK: checked by the core program
+    pub registry_institution: Option<UncheckedAccount<'info>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AllocateClaim<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik_account.wallet.as_ref()],
+        bump = mustahik_account.bump
+    )]
+    pub mustahik_account: Account<'info, Mustahik>,
+    #[account(
+        init_if_needed,
+        payer = amil,
+        space = 8 + Claim::LEN,
+        seeds = [b"claim", mustahik_account.key().as_ref()],
+        bump
+    )]
+    pub claim: Account<'info, Claim>,
+    #[account(address = program_token_account.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = amil,
+        seeds = [b"claim_vault", claim.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = claim
+    )]
+    pub claim_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// Second approver, required only when the allocation exceeds a cap.
+    pub override_approver: Option<Signer<'info>>,
+    #[account(
+        seeds = [b"officer", override_approver.as_ref().map(|approver| approver.key()).unwrap_or_default().as_ref()],
+        bump = override_officer.bump
+    )]
+    pub override_officer: Option<Account<'info, Officer>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAllocation<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub mustahik: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik.key().as_ref()],
+        bump = mustahik_account.bump
+    )]
+    pub mustahik_account: Account<'info, Mustahik>,
+    #[account(
+        mut,
+        seeds = [b"claim", mustahik_account.key().as_ref()],
+        bump = claim.bump
+    )]
+    pub claim: Account<'info, Claim>,
+    #[account(mut, address = claim.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub claim_vault: Account<'info, TokenAccount>,
+    #[account(address = claim_vault.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = mustahik,
+        associated_token::mint = mint,
+        associated_token::authority = mustahik
+    )]
+    pub mustahik_token_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// Core identity registry, required once the mustahik is enrolled
+    pub core_program: Option<Program<'info, ZakachainCore>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_identity: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_enrolment: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    pub registry_institution: Option<UncheckedAccount<'info>>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimAllocation<'info> {
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(mut, address = claim.mustahik_account @ ZakaChainError::InvalidMustahik)]
+    pub mustahik_account: Account<'info, Mustahik>,
+    #[account(
+        mut,
+        seeds = [b"claim", claim.mustahik_account.as_ref()],
+        bump = claim.bump
+    )]
+    pub claim: Account<'info, Claim>,
+    #[account(mut, address = claim.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub claim_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = claim_vault.mint,
+        token::authority = state
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(merkle_root: [u8; 32], leaf_count: u32)]
+pub struct CreateRound<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(mut)]
+    pub amil: Signer<'info>,
+    #[account(
+        init,
+        payer = amil,
+        space = 8 + Round::LEN + Round::bitmap_len(leaf_count),
+        seeds = [b"round", state.round_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub round: Account<'info, Round>,
+    #[account(address = program_token_account.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = amil,
+        seeds = [b"round_vault", round.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = round
+    )]
+    pub round_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimFromRound<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    #[account(
+        mut,
+        seeds = [b"round", round.id.to_le_bytes().as_ref()],
+        bump = round.bump
+    )]
+    pub round: Account<'info, Round>,
+    #[account(mut, address = round.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub round_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"mustahik", state.key().as_ref(), mustahik_account.wallet.as_ref()],
+        bump = mustahik_account.bump
+    )]
+    pub mustahik_account: Account<'info, Mustahik>,
+    #[account(
+        mut,
+        constraint = mustahik_token_account.owner == mustahik_account.wallet @ ZakaChainError::InvalidTokenAccount
+    )]
+    pub mustahik_token_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"hijri_calendar"], bump, seeds::program = zakachain_core::ID)]
+    pub hijri_calendar: Account<'info, HijriCalendar>,
+    /// Core identity registry, required once the mustahik is enrolled
+    pub core_program: Option<Program<'info, ZakachainCore>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_identity: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    #[account(mut)]
+    pub registry_enrolment: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the core program
+    pub registry_institution: Option<UncheckedAccount<'info>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRound<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"round", round.id.to_le_bytes().as_ref()],
+        bump = round.bump
+    )]
+    pub round: Account<'info, Round>,
+    #[account(mut, address = round.vault @ ZakaChainError::InvalidTokenAccount)]
+    pub round_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = round_vault.mint,
+        token::authority = state
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawAmilFees<'info> {
+    #[account(mut)]
+    pub state: Account<'info, ZakaChainState>,
+    pub amil: Signer<'info>,
+    #[account(mut)]
+    pub program_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
     pub amil_token_account: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-#[account]
-pub struct ZakaChainState {
-    pub amil: Pubkey,
-    pub amil_fee_percentage: u8,
-    pub total_zakat_collected: u64,
-    pub total_zakat_distributed: u64,
-    pub total_amil_fees_collected: u64,
-    pub is_initialized: bool,
-    pub last_withdrawal_timestamp: i64,
-    pub manual_withdrawal_count: u32,
-    pub mustahiks: Vec<Pubkey>,
+#[account]
+pub struct ZakaChainState {
+    pub amil: Pubkey,
+    pub amil_fee_percentage: u8,
+    pub total_zakat_collected: u64,
+    pub total_zakat_distributed: u64,
+    pub total_amil_fees_collected: u64,
+    pub is_initialized: bool,
+    pub last_withdrawal_timestamp: i64,
+    pub manual_withdrawal_count: u32,
+    pub mustahik_count: u64,
+    pub refund_window: i64,
+    pub receipt_count: u64,
+    pub invoice_count: u64,
+    pub period_year: u16,
+    pub period_month: u8,
+    pub period_zakat_collected: u64,
+    pub period_amil_fees_collected: u64,
+    pub period_zakat_distributed: u64,
+    /// Totals for the Hijri year of `period_year`
+    pub year_zakat_collected: u64,
+    pub year_amil_fees_collected: u64,
+    pub year_zakat_distributed: u64,
+    pub max_relayer_fee: u64,
+    pub campaign_count: u64,
+    pub nisab: u64,
+    /// Disbursement caps applying to every mustahik of an asnaf, indexed by `Asnaf`
+    pub asnaf_caps: [DisbursementCaps; ASNAF_COUNT],
+    pub round_count: u64,
+}
+
+impl ZakaChainState {
+    pub const LEN: usize = 32 + 1 + 8 + 8 + 8 + 1 + 8 + 4 + 8 + 8 + 8 + 8 + 2 + 1 + 8 + 8 + 8
+        + 8 + 8 + 8 // Hijri year totals
+        + 8 + 8 + 8
+        + (DisbursementCaps::LEN * ASNAF_COUNT)
+        + 8;
+}
+
+/// State layout of the original release, read once by `migrate_state`.
+#[derive(AnchorDeserialize)]
+pub struct LegacyZakaChainState {
+    pub amil: Pubkey,
+    pub amil_fee_percentage: u8,
+    pub total_zakat_collected: u64,
+    pub total_zakat_distributed: u64,
+    pub total_amil_fees_collected: u64,
+    pub is_initialized: bool,
+    pub last_withdrawal_timestamp: i64,
+    pub manual_withdrawal_count: u32,
+    pub mustahiks: Vec<Pubkey>,
+}
+
+impl LegacyZakaChainState {
+    /// As allocated by the original release
+    pub const LEN: usize = 32 + 1 + 8 + 8 + 8 + 1 + 8 + 4 + (32 * LEGACY_MAX_MUSTAHIKS);
+}
+
+/// Mustahiks of the original release still waiting for
+/// `migrate_legacy_mustahik`.
+#[account]
+pub struct LegacyMustahiks {
+    pub wallets: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl LegacyMustahiks {
+    pub const LEN: usize = 4 + (32 * LEGACY_MAX_MUSTAHIKS) + 1;
+}
+
+#[account]
+pub struct Receipt {
+    pub id: u64,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+    pub refunded: bool,
+    pub agent: Pubkey,
+    pub agent_commission: u64,
+    /// Whose zakat this is; differs from `payer` for payroll collections.
+    pub muzakki: Pubkey,
+    /// The protocol integration that paid through CPI, if any.
+    pub integration: Pubkey,
+    /// The payer's payment id; with `index` it seeds the receipt PDA
+    pub payment_id: [u8; 16],
+    /// Position within a multi-receipt payment such as a payroll; 0 otherwise
+    pub index: u8,
+    /// The fund the payment went to; default for the general pool
+    pub fund: Pubkey,
+    /// The invoice the payment settled, if any
+    pub invoice: Pubkey,
+    /// The obligation the payment was an installment of, if any
+    pub obligation: Pubkey,
+    /// Whether the payment was added to the muzakki profile's `total_paid`
+    pub profile_credited: bool,
+    /// Hijri month of the payment, as the calendar stood when it was made
+    pub paid_year: u16,
+    pub paid_month: u8,
+    pub bump: u8,
+}
+
+impl Receipt {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 1 + 32 + 8 + 32 + 32 + 16 + 1 + 32 + 32 + 32 + 1 + 2 + 1 + 1;
+}
+
+#[account]
+pub struct Mustahik {
+    pub institution: Pubkey,
+    pub wallet: Pubkey,
+    pub asnaf: Asnaf,
+    pub status: MustahikStatus,
+    pub applicant: Pubkey,
+    pub applied_at: i64,
+    pub surveyor: Pubkey,
+    pub surveyed_at: i64,
+    pub assessment_hash: [u8; 32],
+    pub survey_recommended: bool,
+    pub approver: Pubkey,
+    pub approved_at: i64,
+    pub eligible_until: i64,
+    pub last_expiry_notice: i64,
+    pub total_received: u64,
+    pub payment_count: u64,
+    pub last_payment_at: i64,
+    /// Hijri month the period totals below belong to
+    pub period_year: u16,
+    pub period_month: u8,
+    /// Received, or allocated and not reclaimed, this Hijri month and year
+    pub month_received: u64,
+    pub year_received: u64,
+    pub caps: DisbursementCaps,
+    /// sha256(salt || NIK); the salt stays with the institution
+    pub identity_hash: [u8; 32],
+    /// Pointer to the off-chain record, e.g. an encrypted document URI
+    pub data_uri: String,
+    /// Keyed hash of the NIK from the consortium's hashing service, as attested
+    /// by the surveyor; zero until surveyed
+    pub dedup_hash: [u8; 32],
+    /// Enrolment in the core identity registry, once enrolled
+    pub enrolment: Pubkey,
+    pub bump: u8,
+}
+
+impl Mustahik {
+    pub const LEN: usize = 32 + 32 + 1 + 1 + 32 + 8 + 32 + 8 + 32 + 1 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 2 + 1 + 8 + 8
+        + DisbursementCaps::LEN
+        + 32
+        + 4
+        + MAX_DATA_URI_LEN
+        + 32
+        + 32
+        + 1;
+
+    /// Received this Hijri month and year, as of `today`.
+    pub fn period_received(&self, today: HijriDate) -> (u64, u64) {
+        hijri::period_totals(
+            self.period_year,
+            self.period_month,
+            self.month_received,
+            self.year_received,
+            today,
+        )
+    }
+}
+
+#[account]
+pub struct Round {
+    pub id: u64,
+    pub merkle_root: [u8; 32],
+    pub vault: Pubkey,
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+    pub leaf_count: u32,
+    pub expires_at: i64,
+    pub closed: bool,
+    pub bump: u8,
+    /// One bit per leaf, set once the leaf is claimed
+    pub claimed: Vec<u8>,
+}
+
+impl Round {
+    /// Without the bitmap contents
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 4 + 8 + 1 + 1 + 4;
+
+    pub fn bitmap_len(leaf_count: u32) -> usize {
+        (leaf_count as usize).div_ceil(8)
+    }
+
+    pub fn is_claimed(&self, index: u32) -> bool {
+        self.claimed[(index / 8) as usize] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set_claimed(&mut self, index: u32) {
+        self.claimed[(index / 8) as usize] |= 1 << (index % 8);
+    }
+}
+
+/// Escrow of amounts allocated to a mustahik and not yet claimed.
+#[account]
+pub struct Claim {
+    pub mustahik: Pubkey,
+    pub mustahik_account: Pubkey,
+    pub vault: Pubkey,
+    /// Currently claimable
+    pub amount: u64,
+    pub total_allocated: u64,
+    pub total_claimed: u64,
+    pub expires_at: i64,
+    /// Hijri month in which the unclaimed amount was last topped up
+    pub period_year: u16,
+    pub period_month: u8,
+    /// Parts of the unclaimed amount counted in the mustahik's totals for
+    /// that month and year
+    pub month_reserved: u64,
+    pub year_reserved: u64,
+    pub bump: u8,
+}
+
+impl Claim {
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 8 + 8 + 2 + 1 + 8 + 8 + 1;
+
+    /// Reserved in the mustahik's totals for this Hijri month and year, as of
+    /// `today`.
+    pub fn period_reserved(&self, today: HijriDate) -> (u64, u64) {
+        hijri::period_totals(
+            self.period_year,
+            self.period_month,
+            self.month_reserved,
+            self.year_reserved,
+            today,
+        )
+    }
+}
+
+/// Disbursement limits in base units; zero means no limit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisbursementCaps {
+    pub per_payment: u64,
+    pub per_month: u64,
+    pub per_year: u64,
+}
+
+impl DisbursementCaps {
+    pub const LEN: usize = 8 + 8 + 8;
+
+    /// Whether paying `payment` to `mustahik` today, of which `amount` is not
+    /// yet counted in their month and year totals, would go over a cap.
+    pub fn is_exceeded(&self, mustahik: &Mustahik, today: HijriDate, payment: u64, amount: u64) -> bool {
+        let (month_received, year_received) = mustahik.period_received(today);
+        let over = |cap: u64, total: u64| cap > 0 && total > cap;
+        over(self.per_payment, payment)
+            || over(self.per_month, month_received.saturating_add(amount))
+            || over(self.per_year, year_received.saturating_add(amount))
+    }
+}
+
+/// The eight groups eligible for zakat (QS. At-Taubah: 60).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Asnaf {
+    Fakir,
+    Miskin,
+    Amil,
+    Muallaf,
+    Riqab,
+    Gharimin,
+    FiSabilillah,
+    IbnuSabil,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MustahikStatus {
+    Applied,
+    Surveyed,
+    Active,
+    Rejected,
+    Suspended,
+}
+
+impl MustahikStatus {
+    /// Approved and not since rejected; counted in `mustahik_count`.
+    pub fn is_approved(&self) -> bool {
+        matches!(self, MustahikStatus::Active | MustahikStatus::Suspended)
+    }
+}
+
+#[account]
+pub struct Officer {
+    pub wallet: Pubkey,
+    pub role: OfficerRole,
+    pub bump: u8,
+}
+
+impl Officer {
+    pub const LEN: usize = 32 + 1 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OfficerRole {
+    FieldOfficer,
+    Surveyor,
+    Approver,
+}
+
+#[account]
+pub struct ProtocolIntegration {
+    pub authority: Pubkey,
+    pub program_id: Pubkey,
+    pub name: String,
+    pub is_active: bool,
+    pub total_collected: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl ProtocolIntegration {
+    pub const LEN: usize = 32 + 32 + 4 + MAX_INTEGRATION_NAME_LEN + 1 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct PriceFeed {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub price: u64,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl PriceFeed {
+    pub const LEN: usize = 32 + 1 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct CollectionAgent {
+    pub wallet: Pubkey,
+    pub name: String,
+    pub commission_bps: u16,
+    pub total_collected: u64,
+    pub total_amil_fees: u64,
+    pub commission_accrued: u64,
+    pub commission_paid: u64,
+    pub is_active: bool,
+    pub bump: u8,
+}
+
+impl CollectionAgent {
+    pub const LEN: usize = 32 + 4 + MAX_AGENT_NAME_LEN + 2 + 8 + 8 + 8 + 8 + 1 + 1;
+}
+
+#[account]
+pub struct PaymentMarker {
+    pub payer: Pubkey,
+    pub payment_id: [u8; 16],
+    /// The receipt issued for the payment, or the contribution for a campaign donation
+    pub receipt: Pubkey,
+    pub timestamp: i64,
+    pub bump: u8,
+}
+
+impl PaymentMarker {
+    pub const LEN: usize = 32 + 16 + 32 + 8 + 1;
+}
+
+#[account]
+pub struct Fund {
+    pub fund_id: u8,
+    pub name: String,
+    pub amil_fee_percentage: u8,
+    pub window: CollectionWindow,
+    /// The token the fund is collected in
+    pub mint: Pubkey,
+    pub total_collected: u64,
+    pub total_amil_fees_collected: u64,
+    pub bump: u8,
+}
+
+impl Fund {
+    pub const LEN: usize = 1 + 4 + MAX_FUND_NAME_LEN + 1 + 1 + 32 + 8 + 8 + 1;
+}
+
+/// Hijri dates during which a fund accepts payments.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CollectionWindow {
+    Anytime,
+    /// Zakat al-fitr: Ramadan until Eid al-Fitr (1 Shawwal)
+    Fitrah,
+    /// Qurban: Eid al-Adha and the days of tashriq
+    EidAlAdha,
+}
+
+impl CollectionWindow {
+    pub fn is_open(&self, today: &HijriDate) -> bool {
+        match self {
+            CollectionWindow::Anytime => true,
+            CollectionWindow::Fitrah => today.is_fitrah_window(),
+            CollectionWindow::EidAlAdha => today.is_eid_al_adha_window(),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FundPart {
+    pub fund: Pubkey,
+    pub amount: u64,
+}
+
+#[account]
+pub struct Invoice {
+    pub id: u64,
+    pub payer: Pubkey,
+    pub fund: Pubkey,
+    pub amount: u64,
+    pub amount_paid: u64,
+    pub due_date: i64,
+    pub reference: String,
+    pub status: InvoiceStatus,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Invoice {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 8 + 4 + MAX_INVOICE_REFERENCE_LEN + 1 + 8 + 1;
+}
+
+#[account]
+pub struct Obligation {
+    pub muzakki: Pubkey,
+    pub fund: Pubkey,
+    pub haul_year: u16,
+    pub amount: u64,
+    pub amount_paid: u64,
+    pub created_at: i64,
+    pub completed_at: i64,
+    pub bump: u8,
+}
+
+impl Obligation {
+    pub const LEN: usize = 32 + 32 + 2 + 8 + 8 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct MuzakkiProfile {
+    pub owner: Pubkey,
+    pub haul_start: i64,
+    /// Hijri date of `haul_start`, as the calendar stood when it was set
+    pub haul_start_year: u16,
+    pub haul_start_month: u8,
+    pub haul_start_day: u8,
+    pub last_completed_at: i64,
+    /// Hijri year of the last haul closed by a completed obligation
+    pub completed_haul_year: u16,
+    pub last_reminded_due: i64,
+    pub created_at: i64,
+    pub total_paid: u64,
+    pub last_paid_at: i64,
+    pub bump: u8,
+}
+
+impl MuzakkiProfile {
+    pub const LEN: usize = 32 + 8 + 2 + 1 + 1 + 8 + 2 + 8 + 8 + 8 + 8 + 1;
+
+    pub fn haul_start_date(&self) -> HijriDate {
+        HijriDate {
+            year: self.haul_start_year,
+            month: self.haul_start_month,
+            day: self.haul_start_day,
+        }
+    }
+
+    fn set_haul_start(&mut self, haul_start: i64, started_on: HijriDate) {
+        self.haul_start = haul_start;
+        self.haul_start_year = started_on.year;
+        self.haul_start_month = started_on.month;
+        self.haul_start_day = started_on.day;
+    }
+}
+
+#[account]
+pub struct MatchingPool {
+    pub sponsor: Pubkey,
+    pub fund: Pubkey,
+    pub vault: Pubkey,
+    pub match_ratio_bps: u16,
+    pub per_donor_cap: u64,
+    pub total_cap: u64,
+    pub total_matched: u64,
+    pub end_timestamp: i64,
+    pub bump: u8,
+}
+
+impl MatchingPool {
+    pub const LEN: usize = 32 + 32 + 32 + 2 + 8 + 8 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct DonorMatch {
+    pub pool: Pubkey,
+    pub donor: Pubkey,
+    pub matched: u64,
+    pub bump: u8,
+}
+
+impl DonorMatch {
+    pub const LEN: usize = 32 + 32 + 8 + 1;
+}
+
+#[account]
+pub struct Campaign {
+    pub id: u64,
+    pub title: String,
+    pub target: u64,
+    pub deadline: i64,
+    pub beneficiaries: Vec<Beneficiary>,
+    pub vault: Pubkey,
+    pub raised: u64,
+    pub refunded: u64,
+    pub released: bool,
+    pub bump: u8,
+}
+
+impl Campaign {
+    pub const LEN: usize = 8 + 4 + MAX_CAMPAIGN_TITLE_LEN + 8 + 8
+        + 4 + (Beneficiary::LEN * MAX_BENEFICIARIES)
+        + 32 + 8 + 8 + 1 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Beneficiary {
+    pub wallet: Pubkey,
+    pub share_bps: u16,
+}
+
+impl Beneficiary {
+    pub const LEN: usize = 32 + 2;
+}
+
+#[account]
+pub struct Contribution {
+    pub campaign: Pubkey,
+    pub donor: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+impl Contribution {
+    pub const LEN: usize = 32 + 32 + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceStatus {
+    Open,
+    PartiallyPaid,
+    Paid,
+    Cancelled,
+    Overdue,
+}
+
+#[event]
+pub struct StateMigrated {
+    /// Mustahiks registered in the legacy state, to be migrated
+    pub legacy_mustahiks: Vec<Pubkey>,
+    pub timestamp: i64,
 }
 
-impl ZakaChainState {
-    pub const LEN: usize = 32 + 1 + 8 + 8 + 8 + 1 + 8 + 4 + (32 * MAX_MUSTAHIKS);
+#[event]
+pub struct LegacyMustahikMigrated {
+    pub mustahik: Pubkey,
+    pub asnaf: Asnaf,
+    pub eligible_until: i64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct ZakatCollected {
+    pub payer: Pubkey,
+    pub receipt: Pubkey,
+    pub payment_id: [u8; 16],
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RelayerReimbursed {
+    pub receipt: Pubkey,
+    pub donor: Pubkey,
+    pub relayer: Pubkey,
+    pub relayer_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MatchingPoolCreated {
+    pub pool: Pubkey,
+    pub sponsor: Pubkey,
+    pub fund: Pubkey,
+    pub match_ratio_bps: u16,
+    pub per_donor_cap: u64,
+    pub total_cap: u64,
+    pub end_timestamp: i64,
+    pub deposit: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DonationMatched {
+    pub pool: Pubkey,
+    pub donor: Pubkey,
+    pub fund: Pubkey,
+    pub donation: u64,
+    pub matched: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MatchingPoolClosed {
+    pub pool: Pubkey,
+    pub sponsor: Pubkey,
+    pub total_matched: u64,
+    pub returned: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignCreated {
+    pub campaign: Pubkey,
+    pub title: String,
+    pub target: u64,
+    pub deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignDonation {
+    pub campaign: Pubkey,
+    pub donor: Pubkey,
+    pub amount: u64,
+    pub raised: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignReleased {
+    pub campaign: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignRefunded {
+    pub campaign: Pubkey,
+    pub donor: Pubkey,
+    pub contributed: u64,
+    pub refunded: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct IntegrationRegistered {
+    pub integration: Pubkey,
+    pub authority: Pubkey,
+    pub program_id: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct IntegrationCollection {
+    pub integration: Pubkey,
+    pub program_id: Pubkey,
+    pub user: Pubkey,
+    pub receipt: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NisabUpdated {
+    pub nisab: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PriceUpdated {
+    pub mint: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentRegistered {
+    pub agent: Pubkey,
+    pub wallet: Pubkey,
+    pub commission_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentCollection {
+    pub agent: Pubkey,
+    pub receipt: Pubkey,
+    pub amount: u64,
+    pub commission: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentUpdated {
+    pub agent: Pubkey,
+    pub commission_bps: u16,
+    pub is_active: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentCommissionPaid {
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentCommissionClawedBack {
+    pub agent: Pubkey,
+    pub receipt: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentCommissionRepaid {
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PayrollContribution {
+    pub employer: Pubkey,
+    pub muzakki: Pubkey,
+    pub receipt: Pubkey,
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PayrollCollected {
+    pub employer: Pubkey,
+    pub payment_id: [u8; 16],
+    pub employees: u16,
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ZakatRefunded {
+    pub receipt: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundCreated {
+    pub fund: Pubkey,
+    pub fund_id: u8,
+    pub amil_fee_percentage: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundContribution {
+    pub payer: Pubkey,
+    pub fund: Pubkey,
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InvoiceCreated {
+    pub invoice: Pubkey,
+    pub payer: Pubkey,
+    pub fund: Pubkey,
+    pub amount: u64,
+    pub due_date: i64,
+    pub reference: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InvoicePaid {
+    pub invoice: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub amil_fee: u64,
+    pub remaining: u64,
+    pub status: InvoiceStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InvoiceCancelled {
+    pub invoice: Pubkey,
+    pub amount_paid: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InvoiceOverdue {
+    pub invoice: Pubkey,
+    pub remaining: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MuzakkiRegistered {
+    pub muzakki: Pubkey,
+    pub haul_start: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HaulStartUpdated {
+    pub muzakki: Pubkey,
+    pub haul_start: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ZakatDue {
+    pub muzakki: Pubkey,
+    pub profile: Pubkey,
+    pub haul_start: i64,
+    pub due_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ObligationRegistered {
+    pub obligation: Pubkey,
+    pub muzakki: Pubkey,
+    pub fund: Pubkey,
+    pub haul_year: u16,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ObligationInstallmentPaid {
+    pub obligation: Pubkey,
     pub payer: Pubkey,
     pub amount: u64,
     pub amil_fee: u64,
+    pub remaining: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ObligationCompleted {
+    pub obligation: Pubkey,
+    pub muzakki: Pubkey,
+    pub haul_year: u16,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HijriPeriodClosed {
+    pub year: u16,
+    pub month: u8,
+    pub zakat_collected: u64,
+    pub amil_fees_collected: u64,
+    pub zakat_distributed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HijriYearClosed {
+    pub year: u16,
+    pub zakat_collected: u64,
+    pub amil_fees_collected: u64,
+    pub zakat_distributed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfficerRegistered {
+    pub officer: Pubkey,
+    pub role: OfficerRole,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MustahikApplied {
+    pub mustahik: Pubkey,
+    pub applicant: Pubkey,
+    pub asnaf: Asnaf,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MustahikSurveyed {
+    pub mustahik: Pubkey,
+    pub surveyor: Pubkey,
+    pub assessment_hash: [u8; 32],
+    pub recommended: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MustahikReviewed {
+    pub mustahik: Pubkey,
+    pub approver: Pubkey,
+    pub approved: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MustahikReverified {
+    pub mustahik: Pubkey,
+    pub surveyor: Pubkey,
+    pub assessment_hash: [u8; 32],
+    pub eligible: bool,
+    pub eligible_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MustahikEligibilityExpiring {
+    pub mustahik: Pubkey,
+    pub account: Pubkey,
+    pub eligible_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CapsUpdated {
+    /// None for asnaf-wide caps
+    pub mustahik: Option<Pubkey>,
+    pub asnaf: Asnaf,
+    pub caps: DisbursementCaps,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoundCreated {
+    pub round: Pubkey,
+    pub merkle_root: [u8; 32],
+    pub leaf_count: u32,
+    pub total_amount: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoundClaimed {
+    pub round: Pubkey,
+    pub mustahik: Pubkey,
+    pub index: u32,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoundClosed {
+    pub round: Pubkey,
+    pub claimed_amount: u64,
+    pub leftover: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ClaimAllocated {
+    pub mustahik: Pubkey,
+    pub claim: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AllocationClaimed {
+    pub mustahik: Pubkey,
+    pub claim: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AllocationReclaimed {
+    pub mustahik: Pubkey,
+    pub claim: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CapOverridden {
+    pub mustahik: Pubkey,
+    pub approver: Pubkey,
+    pub amount: u64,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct MustahikAdded {
+pub struct MustahikUpdated {
     pub mustahik: Pubkey,
+    pub status: MustahikStatus,
     pub timestamp: i64,
 }
 
@@ -337,4 +5362,126 @@ pub enum ZakaChainError {
     InvalidMustahik,
     #[msg("Insufficient funds")]
     InsufficientFunds,
+    #[msg("Invalid refund window")]
+    InvalidRefundWindow,
+    #[msg("Refund window has expired")]
+    RefundWindowExpired,
+    #[msg("Payment has already been refunded")]
+    AlreadyRefunded,
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
+    #[msg("Fund name is too long (max 32 characters)")]
+    FundNameTooLong,
+    #[msg("Invalid fund account")]
+    InvalidFund,
+    #[msg("Invalid split: parts must be non-empty, unique and match the fund accounts")]
+    InvalidSplit,
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Amil fee exceeds the payer's maximum")]
+    FeeExceedsMaximum,
+    #[msg("Net amount does not match the payer's expectation")]
+    UnexpectedNetAmount,
+    #[msg("Reference is too long (max 64 characters)")]
+    ReferenceTooLong,
+    #[msg("Due date must be in the future")]
+    InvalidDueDate,
+    #[msg("Invoice is not payable")]
+    InvoiceNotPayable,
+    #[msg("Payment exceeds the invoice's remaining amount")]
+    InvoiceOverpayment,
+    #[msg("Invoice is not past its due date")]
+    InvoiceNotOverdue,
+    #[msg("Obligation has already been paid in full")]
+    ObligationCompleted,
+    #[msg("Payment exceeds the obligation's remaining amount")]
+    ObligationOverpayment,
+    #[msg("Haul start cannot be in the future")]
+    InvalidHaulStart,
+    #[msg("Invalid muzakki profile account")]
+    InvalidProfile,
+    #[msg("Fund is outside its Hijri collection window")]
+    OutsideCollectionWindow,
+    #[msg("Relayer fee exceeds the allowed maximum")]
+    RelayerFeeTooHigh,
+    #[msg("Invalid matching pool parameters")]
+    InvalidMatchingPool,
+    #[msg("Matching pool has not ended yet")]
+    MatchingPoolActive,
+    #[msg("Campaign title is too long (max 64 characters)")]
+    CampaignTitleTooLong,
+    #[msg("Beneficiaries must be 1-5 wallets with shares summing to 100%")]
+    InvalidBeneficiaries,
+    #[msg("Campaign is not accepting donations")]
+    CampaignNotActive,
+    #[msg("Campaign has not reached its target")]
+    CampaignTargetNotReached,
+    #[msg("Contribution is not refundable")]
+    CampaignNotRefundable,
+    #[msg("Agent name is too long (max 32 characters)")]
+    AgentNameTooLong,
+    #[msg("Invalid commission share")]
+    InvalidCommission,
+    #[msg("Collection agent is inactive")]
+    AgentInactive,
+    #[msg("Invalid collection agent account")]
+    InvalidAgent,
+    #[msg("Invalid payroll batch")]
+    InvalidPayroll,
+    #[msg("Invalid receipt account")]
+    InvalidReceipt,
+    #[msg("Nisab has not been set")]
+    NisabNotSet,
+    #[msg("Invalid holdings accounts")]
+    InvalidHoldings,
+    #[msg("Price feed does not match the token mint")]
+    InvalidPriceFeed,
+    #[msg("Price is too old")]
+    StalePrice,
+    #[msg("Integration name is too long (max 32 characters)")]
+    IntegrationNameTooLong,
+    #[msg("Protocol integration is inactive")]
+    IntegrationInactive,
+    #[msg("Token account is not delegated to the protocol")]
+    InvalidDelegate,
+    #[msg("Mustahik is not active")]
+    MustahikInactive,
+    #[msg("Mustahik is not in the required status")]
+    InvalidMustahikStatus,
+    #[msg("Mustahik eligibility has expired")]
+    EligibilityExpired,
+    #[msg("Disbursement cap exceeded")]
+    CapExceeded,
+    #[msg("Cap override requires a second approver")]
+    InvalidOverride,
+    #[msg("Claim expiry must be in the future")]
+    InvalidClaimExpiry,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Claim has expired")]
+    ClaimExpired,
+    #[msg("Claim has not expired yet")]
+    ClaimNotExpired,
+    #[msg("Invalid distribution round")]
+    InvalidRound,
+    #[msg("Distribution round is closed")]
+    RoundClosed,
+    #[msg("Invalid Merkle proof")]
+    InvalidProof,
+    #[msg("Leaf has already been claimed")]
+    AlreadyClaimed,
+    #[msg("Invalid distribution batch")]
+    InvalidBatch,
+    #[msg("Refund exceeds the recorded totals")]
+    RefundExceedsTotals,
+    #[msg("State account is not in the legacy layout")]
+    StateAlreadyMigrated,
+    #[msg("Collection was not invoked by the integration's registered program")]
+    IntegrationProgramMismatch,
+    #[msg("Identity hash does not match the application")]
+    IdentityMismatch,
+    #[msg("Registry accounts are missing or do not match the mustahik's enrolment")]
+    InvalidRegistryAccounts,
+    #[msg("No deduplication hash has been attested for this mustahik")]
+    DedupHashMissing,
 } 
\ No newline at end of file
@@ -1,6 +1,7 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Zakachain } from "../target/types/zakachain";
+import { ZakachainCore } from "../target/types/zakachain_core";
 import {
   TOKEN_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -8,9 +9,15 @@ import {
   createAssociatedTokenAccount,
   mintTo,
   getAccount,
+  transfer,
+  approve,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
 } from "@solana/spl-token";
 import { PublicKey, Keypair, LAMPORTS_PER_SOL } from "@solana/web3.js";
 import { assert } from "chai";
+import { createHash, createHmac } from "crypto";
+import { keccak_256 } from "@noble/hashes/sha3";
 
 describe("zakachain", () => {
   // Configure the client to use the local cluster
@@ -18,11 +25,14 @@ describe("zakachain", () => {
   anchor.setProvider(provider);
 
   const program = anchor.workspace.Zakachain as Program<Zakachain>;
+  const coreProgram = anchor.workspace.ZakachainCore as Program<ZakachainCore>;
 
   // Test accounts
   const amil = Keypair.generate();
   const payer = Keypair.generate();
   const mustahik = Keypair.generate();
+  const surveyor = Keypair.generate();
+  const approver = Keypair.generate();
   const amilOperational = Keypair.generate();
 
   // Token accounts
@@ -35,8 +45,58 @@ describe("zakachain", () => {
 
   // Program state account
   let stateAccount: PublicKey;
+  let mustahikAccount: PublicKey;
+
+  // Shared Hijri calendar published on the core program
+  const [coreState] = PublicKey.findProgramAddressSync([Buffer.from("state")], coreProgram.programId);
+  const [hijriCalendar] = PublicKey.findProgramAddressSync(
+    [Buffer.from("hijri_calendar")],
+    coreProgram.programId
+  );
+
+  const [payerProfile] = PublicKey.findProgramAddressSync(
+    [Buffer.from("muzakki"), payer.publicKey.toBuffer()],
+    program.programId
+  );
+
+  // Hijri year of a timestamp on the tabular calendar; the tests run away from
+  // Muharram, so published month starts cannot move the year
+  const hijriYear = (unixTimestamp: number) => {
+    const days = Math.floor(unixTimestamp / 86400) + 492148;
+    return Math.floor((30 * days + 10646) / 10631);
+  };
+
+  // First day of a month on the tabular calendar, in days since the Unix epoch
+  const tabularMonthStart = (year: number, month: number) =>
+    -492148 + Math.floor((59 * (month - 1) + 1) / 2) + (year - 1) * 354 + Math.floor((3 + 11 * year) / 30);
+
+  // Receipts are keyed by the payer's payment id and the leg within the payment
+  const receiptPda = (payerKey: PublicKey, paymentId: number[], index = 0) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), payerKey.toBuffer(), Buffer.from(paymentId), Buffer.from([index])],
+      program.programId
+    )[0];
 
   before(async () => {
+    // The core program owns the Hijri calendar every instruction reads
+    await coreProgram.methods
+      .initialize(25)
+      .accounts({
+        state: coreState,
+        amil: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    await coreProgram.methods
+      .initializeHijriCalendar()
+      .accounts({
+        state: coreState,
+        hijriCalendar,
+        amil: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
     // Airdrop SOL to test accounts
     const signature1 = await provider.connection.requestAirdrop(amil.publicKey, 2 * LAMPORTS_PER_SOL);
     const signature2 = await provider.connection.requestAirdrop(payer.publicKey, 2 * LAMPORTS_PER_SOL);
@@ -60,12 +120,11 @@ describe("zakachain", () => {
       payer.publicKey
     );
 
-    programTokenAccount = await createAssociatedTokenAccount(
-      provider.connection,
-      payer,
-      mint,
-      program.programId
-    );
+    // The vault is owned by the state PDA, which signs every transfer out of it
+    const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+    programTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, statePda, true)
+    ).address;
 
     amilTokenAccount = await createAssociatedTokenAccount(
       provider.connection,
@@ -107,6 +166,10 @@ describe("zakachain", () => {
       [Buffer.from("state")],
       program.programId
     );
+    [mustahikAccount] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mustahik"), stateAccount.toBuffer(), mustahik.publicKey.toBuffer()],
+      program.programId
+    );
 
     await program.methods
       .initialize(amilFeePercentage)
@@ -128,6 +191,70 @@ describe("zakachain", () => {
     assert.ok(state.isInitialized);
   });
 
+  it("Calculates zakat on priced holdings and bounds price decimals", async () => {
+    const priceFeedPda = (tokenMint: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("price"), tokenMint.toBuffer()], program.programId)[0];
+    const updatePrice = (tokenMint: PublicKey, price: number) =>
+      program.methods
+        .updatePrice(new anchor.BN(price))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mint: tokenMint,
+          priceFeed: priceFeedPda(tokenMint),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([amil])
+        .rpc();
+
+    await program.methods
+      .updateNisab(new anchor.BN(100000000))
+      .accounts({ state: stateAccount, authority: amil.publicKey })
+      .signers([amil])
+      .rpc();
+
+    const gold = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+    const goldAccount = await createAssociatedTokenAccount(provider.connection, payer, gold, payer.publicKey);
+    await mintTo(provider.connection, payer, gold, goldAccount, payer, 2000000); // 2 whole tokens
+    await updatePrice(gold, 150000000);
+
+    // 18 decimals scales by 10^18 without overflowing
+    const wrapped = await createMint(provider.connection, payer, payer.publicKey, null, 18);
+    const wrappedAccount = await createAssociatedTokenAccount(provider.connection, payer, wrapped, payer.publicKey);
+    await mintTo(provider.connection, payer, wrapped, wrappedAccount, payer, BigInt("1000000000000000000"));
+    await updatePrice(wrapped, 100000000);
+
+    const calculate = (holdings: [PublicKey, PublicKey][]) =>
+      program.methods
+        .calculateZakat(payer.publicKey)
+        .accounts({ state: stateAccount })
+        .remainingAccounts(
+          holdings.flatMap(([tokenAccount, tokenMint]) => [
+            { pubkey: tokenAccount, isWritable: false, isSigner: false },
+            { pubkey: priceFeedPda(tokenMint), isWritable: false, isSigner: false },
+          ])
+        )
+        .view();
+
+    // 2 * 150 + 1 * 100 = 400 above the 100 nisab; 2.5% of it is due
+    const zakatDue = await calculate([
+      [goldAccount, gold],
+      [wrappedAccount, wrapped],
+    ]);
+    assert.equal(zakatDue.toString(), "10000000");
+    // Holdings exactly at nisab are not below it
+    assert.equal((await calculate([[wrappedAccount, wrapped]])).toString(), "2500000");
+
+    // 10^39 does not fit in a u128, so such a mint cannot be priced
+    const tooPrecise = await createMint(provider.connection, payer, payer.publicKey, null, 39);
+    try {
+      await updatePrice(tooPrecise, 1);
+      assert.fail("Expected error for a mint with too many decimals");
+    } catch (error) {
+      assert.include(error.message, "Price feed does not match the token mint");
+    }
+  });
+
   it("Receives Zakat and calculates Amil fee correctly", async () => {
     const zakatAmount = new anchor.BN(1000000); // 1 token
 
@@ -161,96 +288,2441 @@ describe("zakachain", () => {
     assert.equal(state.totalAmilFeesCollected.toString(), expectedAmilFee.toString());
   });
 
-  it("Adds a mustahik", async () => {
+  it("Refunds a collected payment within the refund window", async () => {
+    const zakatAmount = new anchor.BN(1000000); // 1 token
+    const before = await program.account.zakaChainState.fetch(stateAccount);
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const receipt = receiptPda(payer.publicKey, paymentId);
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+
+    await program.methods
+      .collectZakat(zakatAmount, 25, new anchor.BN(975000), paymentId)
+      .accounts({
+        state: stateAccount,
+        payer: payer.publicKey,
+        payerTokenAccount: payerTokenAccount,
+        programTokenAccount: programTokenAccount,
+        amilTokenAccount: amilTokenAccount,
+        receipt,
+        payment,
+        hijriCalendar,
+        agent: null,
+        fund: null,
+        obligation: null,
+        profile: null,
+        pool: null,
+        poolVault: null,
+        donorMatch: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([payer])
+      .rpc();
+
     await program.methods
-      .addMustahik(mustahik.publicKey)
+      .refundZakat()
       .accounts({
         state: stateAccount,
         amil: amil.publicKey,
+        receipt,
+        payerTokenAccount: payerTokenAccount,
+        programTokenAccount: programTokenAccount,
+        amilTokenAccount: amilTokenAccount,
+        agent: null,
+        profile: null,
+        fund: null,
+        invoice: null,
+        obligation: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
       .signers([amil])
       .rpc();
 
-    // Verify state
-    const state = await program.account.zakaChainState.fetch(stateAccount);
-    assert.ok(state.mustahiks.some((addr: PublicKey) => addr.equals(mustahik.publicKey)));
+    const receiptAccount = await program.account.receipt.fetch(receipt);
+    assert.ok(receiptAccount.refunded);
+
+    const after = await program.account.zakaChainState.fetch(stateAccount);
+    assert.equal(after.totalZakatCollected.toString(), before.totalZakatCollected.toString());
+    assert.equal(after.totalAmilFeesCollected.toString(), before.totalAmilFeesCollected.toString());
+    // Paid and refunded within the same Hijri month
+    assert.equal(after.periodZakatCollected.toString(), before.periodZakatCollected.toString());
+    assert.equal(after.periodAmilFeesCollected.toString(), before.periodAmilFeesCollected.toString());
+    assert.equal(after.yearZakatCollected.toString(), before.yearZakatCollected.toString());
+    assert.equal(after.yearAmilFeesCollected.toString(), before.yearAmilFeesCollected.toString());
   });
 
-  it("Distributes Zakat to a mustahik", async () => {
-    const distributionAmount = new anchor.BN(100000); // 0.1 token
+  it("Publishes a month start only if neighbouring months stay 29 or 30 days", async () => {
+    // Ramadan 1440: tabular Sha'ban has 29 days and Ramadan 30
+    const ramadan = tabularMonthStart(1440, 9);
+    try {
+      await coreProgram.methods
+        .publishMonthStart(1440, 9, new anchor.BN(ramadan - 1))
+        .accounts({ state: coreState, hijriCalendar, authority: provider.wallet.publicKey })
+        .rpc();
+      assert.fail("Expected error for a 28-day Sha'ban");
+    } catch (error) {
+      assert.include(error.message, "Month start would make a month shorter than 29 or longer than 30 days");
+    }
+
+    await coreProgram.methods
+      .publishMonthStart(1440, 9, new anchor.BN(ramadan + 1))
+      .accounts({ state: coreState, hijriCalendar, authority: provider.wallet.publicKey })
+      .rpc();
+    const calendar = await coreProgram.account.hijriCalendar.fetch(hijriCalendar);
+    assert.ok(calendar.monthStarts.some((m) => m.year === 1440 && m.month === 9 && m.unixDay.toNumber() === ramadan + 1));
+  });
+
+  it("Rejects a retried payment with the same payment id", async () => {
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+    const receipt = receiptPda(payer.publicKey, paymentId);
+    const collect = async () => {
+      await program.methods
+        .collectZakat(new anchor.BN(1000000), 25, null, paymentId)
+        .accounts({
+          state: stateAccount,
+          payer: payer.publicKey,
+          payerTokenAccount: payerTokenAccount,
+          programTokenAccount: programTokenAccount,
+          amilTokenAccount: amilTokenAccount,
+          receipt,
+          payment,
+          hijriCalendar,
+          agent: null,
+          fund: null,
+          obligation: null,
+          profile: null,
+          pool: null,
+          poolVault: null,
+          donorMatch: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+    };
+
+    await collect();
+    try {
+      await collect();
+      assert.fail("Expected error for duplicate payment id");
+    } catch (error) {
+      assert.include(error.message, "already in use");
+    }
+  });
+
+  it("Fails to collect when the fee is above the payer's maximum", async () => {
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const receipt = receiptPda(payer.publicKey, paymentId);
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .collectZakat(new anchor.BN(1000000), 10, null, paymentId) // accepts at most 1%
+        .accounts({
+          state: stateAccount,
+          payer: payer.publicKey,
+          payerTokenAccount: payerTokenAccount,
+          programTokenAccount: programTokenAccount,
+          amilTokenAccount: amilTokenAccount,
+          receipt,
+          payment,
+          hijriCalendar,
+          agent: null,
+          fund: null,
+          obligation: null,
+          profile: null,
+          pool: null,
+          poolVault: null,
+          donorMatch: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+      assert.fail("Expected error for fee above maximum");
+    } catch (error) {
+      assert.include(error.message, "Amil fee exceeds the payer's maximum");
+    }
+  });
+
+  it("Collects a relayed payment and reimburses the relayer", async () => {
+    const relayer = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(relayer.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const relayerTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      relayer.publicKey
+    );
+    await program.methods
+      .updateMaxRelayerFee(new anchor.BN(20000))
+      .accounts({ state: stateAccount, authority: amil.publicKey })
+      .signers([amil])
+      .rpc();
+
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const receipt = receiptPda(payer.publicKey, paymentId);
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+    const before = await program.account.zakaChainState.fetch(stateAccount);
+    const collect = (vault: PublicKey) =>
+      program.methods
+        .collectZakatRelayed(new anchor.BN(1000000), new anchor.BN(10000), 25, new anchor.BN(965250), paymentId)
+        .accounts({
+          state: stateAccount,
+          relayer: relayer.publicKey,
+          donor: payer.publicKey,
+          donorTokenAccount: payerTokenAccount,
+          relayerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount: amilTokenAccount,
+          receipt,
+          payment,
+          hijriCalendar,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([relayer, payer])
+        .rpc();
+
+    // The relayer is only reimbursed for a donation that reaches the vault
+    try {
+      await collect(relayerTokenAccount);
+      assert.fail("Expected error for a vault not owned by the state PDA");
+    } catch (error) {
+      assert.include(error.message, "ConstraintTokenOwner");
+    }
+
+    // 1 token, of which 0.01 reimburses the relayer and 2.5% of the rest is the amil fee
+    await collect(programTokenAccount);
+
+    const relayerBalance = await getAccount(provider.connection, relayerTokenAccount);
+    assert.equal(relayerBalance.amount.toString(), "10000");
+    const receiptAccount = await program.account.receipt.fetch(receipt);
+    assert.ok(receiptAccount.payer.equals(payer.publicKey));
+    assert.equal(receiptAccount.amount.toString(), "965250");
+    assert.equal(receiptAccount.amilFee.toString(), "24750");
+    const marker = await program.account.paymentMarker.fetch(payment);
+    assert.ok(marker.receipt.equals(receipt));
+    const after = await program.account.zakaChainState.fetch(stateAccount);
+    assert.equal(after.receiptCount.toNumber(), before.receiptCount.toNumber() + 1);
+
+    // A retry with the same payment id is rejected
+    try {
+      await program.methods
+        .collectZakatRelayed(new anchor.BN(1000000), new anchor.BN(10000), 25, null, paymentId)
+        .accounts({
+          state: stateAccount,
+          relayer: relayer.publicKey,
+          donor: payer.publicKey,
+          donorTokenAccount: payerTokenAccount,
+          relayerTokenAccount,
+          programTokenAccount: programTokenAccount,
+          amilTokenAccount: amilTokenAccount,
+          receipt,
+          payment,
+          hijriCalendar,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([relayer, payer])
+        .rpc();
+      assert.fail("Expected error for duplicate payment id");
+    } catch (error) {
+      assert.include(error.message, "already in use");
+    }
+  });
 
+  it("Pays an invoice in installments and refunds an installment", async () => {
+    const [fund] = PublicKey.findProgramAddressSync([Buffer.from("fund"), Buffer.from([1])], program.programId);
     await program.methods
-      .distributeToMustahik(distributionAmount)
+      .createFund(1, "Infaq", 50, { anytime: {} }) // 5% fee
       .accounts({
         state: stateAccount,
         amil: amil.publicKey,
-        mustahik: mustahik.publicKey,
-        programTokenAccount: programTokenAccount,
-        mustahikTokenAccount: mustahikTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        fund,
+        mint,
+        systemProgram: anchor.web3.SystemProgram.programId,
       })
       .signers([amil])
       .rpc();
 
-    // Verify balances
-    const mustahikTokenBalance = await getAccount(provider.connection, mustahikTokenAccount);
-    assert.equal(mustahikTokenBalance.amount.toString(), distributionAmount.toString());
-
-    // Verify state
     const state = await program.account.zakaChainState.fetch(stateAccount);
-    assert.equal(state.totalZakatDistributed.toString(), distributionAmount.toString());
-  });
+    const [invoice] = PublicKey.findProgramAddressSync(
+      [Buffer.from("invoice"), state.invoiceCount.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const dueDate = new anchor.BN(Math.floor(Date.now() / 1000) + 86400);
+    await program.methods
+      .createInvoice(payer.publicKey, new anchor.BN(2000000), dueDate, "INV-TEST-001")
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        fund,
+        invoice,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([amil])
+      .rpc();
 
-  it("Withdraws for manual distribution", async () => {
-    const withdrawalAmount = new anchor.BN(100000); // 0.1 token
-    const description = "Emergency relief distribution";
+    const pay = async (paymentId: number[], vault = programTokenAccount, feeAccount = amilTokenAccount) => {
+      const [payment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+        program.programId
+      );
+      await program.methods
+        .payInvoice(new anchor.BN(1000000), 50, paymentId)
+        .accounts({
+          state: stateAccount,
+          invoice,
+          fund,
+          payer: payer.publicKey,
+          payerTokenAccount: payerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount: feeAccount,
+          receipt: receiptPda(payer.publicKey, paymentId),
+          payment,
+          hijriCalendar,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+    };
+
+    // Paying into the payer's own accounts does not count towards the invoice
+    try {
+      await pay(Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16)), payerTokenAccount);
+      assert.fail("Expected error for a vault not owned by the state PDA");
+    } catch (error) {
+      assert.include(error.message, "ConstraintTokenOwner");
+    }
+    try {
+      await pay(Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16)), programTokenAccount, payerTokenAccount);
+      assert.fail("Expected error for a fee account not owned by the amil");
+    } catch (error) {
+      assert.include(error.message, "Invalid token account");
+    }
+
+    const firstId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    await pay(firstId);
+    let invoiceAccount = await program.account.invoice.fetch(invoice);
+    assert.equal(invoiceAccount.amountPaid.toString(), "1000000");
+    assert.deepEqual(invoiceAccount.status, { partiallyPaid: {} });
+
+    // Retrying the same installment does not charge the payer twice
+    try {
+      await pay(firstId);
+      assert.fail("Expected error for duplicate payment id");
+    } catch (error) {
+      assert.include(error.message, "already in use");
+    }
+
+    const secondId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    await pay(secondId);
+    invoiceAccount = await program.account.invoice.fetch(invoice);
+    assert.deepEqual(invoiceAccount.status, { paid: {} });
+
+    const receipt = receiptPda(payer.publicKey, secondId);
+    const receiptAccount = await program.account.receipt.fetch(receipt);
+    assert.ok(receiptAccount.fund.equals(fund));
+    assert.ok(receiptAccount.invoice.equals(invoice));
+    assert.equal(receiptAccount.amount.toString(), "950000");
+    assert.equal(receiptAccount.amilFee.toString(), "50000");
 
     await program.methods
-      .withdrawForManualDistribution(withdrawalAmount, description)
+      .refundZakat()
       .accounts({
         state: stateAccount,
         amil: amil.publicKey,
+        receipt,
+        payerTokenAccount: payerTokenAccount,
         programTokenAccount: programTokenAccount,
-        amilOperationalAccount: amilOperationalTokenAccount,
+        amilTokenAccount: amilTokenAccount,
+        agent: null,
+        profile: null,
+        fund,
+        invoice,
+        obligation: null,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
       .signers([amil])
       .rpc();
 
-    // Verify balances
-    const amilOperationalBalance = await getAccount(provider.connection, amilOperationalTokenAccount);
-    assert.equal(amilOperationalBalance.amount.toString(), withdrawalAmount.toString());
+    // The refunded installment reopens the invoice and leaves the fund's totals
+    invoiceAccount = await program.account.invoice.fetch(invoice);
+    assert.equal(invoiceAccount.amountPaid.toString(), "1000000");
+    assert.deepEqual(invoiceAccount.status, { partiallyPaid: {} });
+    const fundAccount = await program.account.fund.fetch(fund);
+    assert.equal(fundAccount.totalCollected.toString(), "950000");
+    assert.equal(fundAccount.totalAmilFeesCollected.toString(), "50000");
   });
 
-  it("Fails to initialize with invalid fee percentage", async () => {
-    const invalidFeePercentage = 126; // 12.6% (above max of 12.5%)
+  it("Reminds a muzakki once when their haul falls due", async () => {
+    // The haul started about 400 days ago, so it fell due last month
+    const haulStart = Math.floor(Date.now() / 1000) - 400 * 86400;
+    await program.methods
+      .registerMuzakki(new anchor.BN(haulStart))
+      .accounts({
+        state: stateAccount,
+        owner: payer.publicKey,
+        profile: payerProfile,
+        hijriCalendar,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([payer])
+      .rpc();
+    // The profile keeps the haul start's Hijri date for later due dates
+    const registered = await program.account.muzakkiProfile.fetch(payerProfile);
+    assert.isAbove(registered.haulStartYear, 1440);
+    assert.isAtLeast(registered.haulStartDay, 1);
 
-    try {
+    const crank = () =>
+      program.methods
+        .emitZakatDue()
+        .accounts({ hijriCalendar })
+        .remainingAccounts([{ pubkey: payerProfile, isWritable: true, isSigner: false }])
+        .rpc();
+    await crank();
+    const reminded = await program.account.muzakkiProfile.fetch(payerProfile);
+    assert.notEqual(reminded.lastRemindedDue.toNumber(), 0);
+    assert.isAtMost(reminded.lastRemindedDue.toNumber(), Math.floor(Date.now() / 1000));
+    await crank();
+    const again = await program.account.muzakkiProfile.fetch(payerProfile);
+    assert.equal(again.lastRemindedDue.toString(), reminded.lastRemindedDue.toString());
+  });
+
+  it("Pays an obligation in installments through collectZakat", async () => {
+    const [fund] = PublicKey.findProgramAddressSync([Buffer.from("fund"), Buffer.from([1])], program.programId);
+    const profile = await program.account.muzakkiProfile.fetch(payerProfile);
+    const paidBefore = profile.totalPaid.toNumber();
+    // The haul falls due one Hijri year after it started
+    const dueYear = hijriYear(profile.haulStart.toNumber()) + 1;
+    const register = async (haulYear: number, amount: number) => {
+      const [obligation] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("obligation"),
+          payer.publicKey.toBuffer(),
+          fund.toBuffer(),
+          new anchor.BN(haulYear).toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
       await program.methods
-        .initialize(invalidFeePercentage)
+        .registerObligation(haulYear, new anchor.BN(amount))
         .accounts({
           state: stateAccount,
-          amil: amil.publicKey,
+          muzakki: payer.publicKey,
+          fund,
+          obligation,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
-        .signers([amil])
+        .signers([payer])
         .rpc();
-      assert.fail("Expected error for invalid fee percentage");
+      return obligation;
+    };
+    const pay = async (obligation: PublicKey, amount: number, vault = programTokenAccount) => {
+      const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+      const [payment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+        program.programId
+      );
+      const receipt = receiptPda(payer.publicKey, paymentId);
+      await program.methods
+        .collectZakat(new anchor.BN(amount), 50, null, paymentId)
+        .accounts({
+          state: stateAccount,
+          payer: payer.publicKey,
+          payerTokenAccount: payerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount: amilTokenAccount,
+          receipt,
+          payment,
+          hijriCalendar,
+          agent: null,
+          fund,
+          obligation,
+          profile: payerProfile,
+          pool: null,
+          poolVault: null,
+          donorMatch: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+      return receipt;
+    };
+
+    // Paying off an earlier year's obligation does not close the current haul
+    const earlier = await register(dueYear - 1, 500000);
+    await pay(earlier, 500000);
+    let profileAccount = await program.account.muzakkiProfile.fetch(payerProfile);
+    assert.equal(profileAccount.haulStart.toString(), profile.haulStart.toString());
+    assert.equal(profileAccount.completedHaulYear, 0);
+
+    // Installments count net of the fund's 5% fee
+    const obligation = await register(dueYear, 1900000);
+    await pay(obligation, 1000000);
+    try {
+      await pay(obligation, 1500000);
+      assert.fail("Expected error for overpaying the obligation");
     } catch (error) {
-      assert.include(error.message, "Invalid fee percentage");
+      assert.include(error.message, "Payment exceeds the obligation's remaining amount");
+    }
+    // An installment paid into the payer's own account is not credited
+    try {
+      await pay(obligation, 1000000, payerTokenAccount);
+      assert.fail("Expected error for a vault not owned by the state PDA");
+    } catch (error) {
+      assert.include(error.message, "ConstraintTokenOwner");
     }
+    let obligationAccount = await program.account.obligation.fetch(obligation);
+    assert.equal(obligationAccount.amountPaid.toString(), "950000");
+    assert.equal(obligationAccount.completedAt.toNumber(), 0);
+
+    const receipt = await pay(obligation, 1000000);
+    obligationAccount = await program.account.obligation.fetch(obligation);
+    assert.equal(obligationAccount.amountPaid.toString(), "1900000");
+    assert.notEqual(obligationAccount.completedAt.toNumber(), 0);
+    const receiptAccount = await program.account.receipt.fetch(receipt);
+    assert.ok(receiptAccount.fund.equals(fund));
+    assert.ok(receiptAccount.obligation.equals(obligation));
+    assert.ok(receiptAccount.profileCredited);
+
+    // Completing the haul's obligation starts the next haul at the anniversary
+    profileAccount = await program.account.muzakkiProfile.fetch(payerProfile);
+    assert.equal(profileAccount.completedHaulYear, dueYear);
+    assert.equal(profileAccount.haulStart.toString(), profile.lastRemindedDue.toString());
+    assert.notEqual(profileAccount.lastCompletedAt.toNumber(), 0);
+    // Net of the fund's 5% fee
+    assert.equal(profileAccount.totalPaid.toNumber() - paidBefore, 475000 + 950000 + 950000);
+
+    // Refunding the last installment reopens the obligation
+    await program.methods
+      .refundZakat()
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        receipt,
+        payerTokenAccount: payerTokenAccount,
+        programTokenAccount: programTokenAccount,
+        amilTokenAccount: amilTokenAccount,
+        agent: null,
+        profile: payerProfile,
+        fund,
+        invoice: null,
+        obligation,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+    obligationAccount = await program.account.obligation.fetch(obligation);
+    assert.equal(obligationAccount.amountPaid.toString(), "950000");
+    assert.equal(obligationAccount.completedAt.toNumber(), 0);
+    profileAccount = await program.account.muzakkiProfile.fetch(payerProfile);
+    assert.equal(profileAccount.totalPaid.toNumber() - paidBefore, 475000 + 950000);
   });
 
-  it("Fails to add mustahik with unauthorized account", async () => {
-    const unauthorizedAccount = Keypair.generate();
+  it("Matches donations to a fund from a sponsor's pool and closes the pool", async () => {
+    const sponsor = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(sponsor.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const sponsorTokenAccount = await createAssociatedTokenAccount(provider.connection, payer, mint, sponsor.publicKey);
+    await mintTo(provider.connection, payer, mint, sponsorTokenAccount, payer, 1000000);
 
-    try {
+    const [fund] = PublicKey.findProgramAddressSync([Buffer.from("fund"), Buffer.from([1])], program.programId);
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("matching_pool"), sponsor.publicKey.toBuffer(), fund.toBuffer()],
+      program.programId
+    );
+    const [poolVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("matching_vault"), pool.toBuffer()],
+      program.programId
+    );
+    const [donorMatch] = PublicKey.findProgramAddressSync(
+      [Buffer.from("donor_match"), pool.toBuffer(), payer.publicKey.toBuffer()],
+      program.programId
+    );
+    const endTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 5);
+    // Matches 50% of each donation, up to 0.3 tokens per donor
+    await program.methods
+      .createMatchingPool(5000, new anchor.BN(300000), new anchor.BN(1000000), endTimestamp, new anchor.BN(1000000))
+      .accounts({
+        state: stateAccount,
+        sponsor: sponsor.publicKey,
+        fund,
+        pool,
+        mint,
+        poolVault,
+        sponsorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([sponsor])
+      .rpc();
+
+    const donate = async (vault = programTokenAccount) => {
+      const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+      const [payment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+        program.programId
+      );
       await program.methods
-        .addMustahik(mustahik.publicKey)
+        .collectZakat(new anchor.BN(1000000), 50, null, paymentId)
         .accounts({
           state: stateAccount,
-          amil: unauthorizedAccount.publicKey,
+          payer: payer.publicKey,
+          payerTokenAccount: payerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount: amilTokenAccount,
+          receipt: receiptPda(payer.publicKey, paymentId),
+          payment,
+          hijriCalendar,
+          agent: null,
+          fund,
+          obligation: null,
+          profile: null,
+          pool,
+          poolVault,
+          donorMatch,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+    };
+
+    // A donor passing their own account as the vault would keep both the
+    // donation and the match
+    try {
+      await donate(payerTokenAccount);
+      assert.fail("Expected error for a vault not owned by the state PDA");
+    } catch (error) {
+      assert.include(error.message, "ConstraintTokenOwner");
+    }
+
+    const fundBefore = await program.account.fund.fetch(fund);
+    await donate();
+    let vault = await getAccount(provider.connection, poolVault);
+    assert.equal(vault.amount.toString(), "700000");
+    const donorMatchAccount = await program.account.donorMatch.fetch(donorMatch);
+    assert.equal(donorMatchAccount.matched.toString(), "300000");
+    const fundAfter = await program.account.fund.fetch(fund);
+    assert.equal(fundAfter.totalCollected.sub(fundBefore.totalCollected).toString(), "1250000");
+
+    // The donor's cap is used up, so the next donation is not matched
+    await donate();
+    vault = await getAccount(provider.connection, poolVault);
+    assert.equal(vault.amount.toString(), "700000");
+
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    await program.methods
+      .closeMatchingPool()
+      .accounts({
+        sponsor: sponsor.publicKey,
+        pool,
+        poolVault,
+        sponsorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([sponsor])
+      .rpc();
+    const sponsorBalance = await getAccount(provider.connection, sponsorTokenAccount);
+    assert.equal(sponsorBalance.amount.toString(), "700000");
+    assert.isNull(await provider.connection.getAccountInfo(pool));
+    assert.isNull(await provider.connection.getAccountInfo(poolVault));
+  });
+
+  // Campaigns are numbered from state.campaignCount
+  const createCampaign = async (target: number, deadline: number, beneficiaries: PublicKey[], shares: number[]) => {
+    const state = await program.account.zakaChainState.fetch(stateAccount);
+    const [campaign] = PublicKey.findProgramAddressSync(
+      [Buffer.from("campaign"), state.campaignCount.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [campaignVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("campaign_vault"), campaign.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .createCampaign(
+        "Test campaign",
+        new anchor.BN(target),
+        new anchor.BN(deadline),
+        beneficiaries.map((wallet, i) => ({ wallet, shareBps: shares[i] }))
+      )
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        campaign,
+        mint,
+        campaignVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([amil])
+      .rpc();
+    return { campaign, campaignVault };
+  };
+
+  const donateToCampaign = async (
+    campaign: PublicKey,
+    campaignVault: PublicKey,
+    donor: Keypair,
+    donorTokenAccount: PublicKey,
+    amount: number,
+    paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16))
+  ) => {
+    const [contribution] = PublicKey.findProgramAddressSync(
+      [Buffer.from("contribution"), campaign.toBuffer(), donor.publicKey.toBuffer()],
+      program.programId
+    );
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), donor.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+    await program.methods
+      .donateToCampaign(new anchor.BN(amount), paymentId)
+      .accounts({
+        campaign,
+        campaignVault,
+        contribution,
+        payment,
+        donor: donor.publicKey,
+        donorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([donor])
+      .rpc();
+    return { contribution, payment };
+  };
+
+  it("Rejects a retried campaign donation with the same payment id", async () => {
+    const { campaign, campaignVault } = await createCampaign(
+      10000000,
+      Math.floor(Date.now() / 1000) + 86400,
+      [mustahik.publicKey],
+      [10000]
+    );
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const { contribution, payment } = await donateToCampaign(
+      campaign,
+      campaignVault,
+      payer,
+      payerTokenAccount,
+      1000000,
+      paymentId
+    );
+    try {
+      await donateToCampaign(campaign, campaignVault, payer, payerTokenAccount, 1000000, paymentId);
+      assert.fail("Expected error for duplicate payment id");
+    } catch (error) {
+      assert.include(error.message, "already in use");
+    }
+    const campaignAccount = await program.account.campaign.fetch(campaign);
+    assert.equal(campaignAccount.raised.toString(), "1000000");
+    const marker = await program.account.paymentMarker.fetch(payment);
+    assert.ok(marker.receipt.equals(contribution));
+  });
+
+  it("Releases a funded campaign by share, with the remainder to the last beneficiary", async () => {
+    const beneficiaries = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    const tokenAccounts = [];
+    for (const beneficiary of beneficiaries) {
+      tokenAccounts.push(
+        await createAssociatedTokenAccount(provider.connection, payer, mint, beneficiary.publicKey)
+      );
+    }
+    const { campaign, campaignVault } = await createCampaign(
+      1000001,
+      Math.floor(Date.now() / 1000) + 86400,
+      beneficiaries.map((b) => b.publicKey),
+      [3333, 3333, 3334]
+    );
+    await donateToCampaign(campaign, campaignVault, payer, payerTokenAccount, 1000001);
+
+    await program.methods
+      .releaseCampaign()
+      .accounts({ campaign, campaignVault, tokenProgram: TOKEN_PROGRAM_ID })
+      .remainingAccounts(tokenAccounts.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+      .rpc();
+
+    // 33.33% of 1000001 rounds down to 333300; the last share takes the rest
+    const balances = [];
+    for (const tokenAccount of tokenAccounts) {
+      balances.push((await getAccount(provider.connection, tokenAccount)).amount.toString());
+    }
+    assert.deepEqual(balances, ["333300", "333300", "333401"]);
+    const vault = await getAccount(provider.connection, campaignVault);
+    assert.equal(vault.amount.toString(), "0");
+    const campaignAccount = await program.account.campaign.fetch(campaign);
+    assert.ok(campaignAccount.released);
+  });
+
+  it("Refunds donors pro rata from the escrow after a missed deadline", async () => {
+    const donor = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(donor.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const donorTokenAccount = await createAssociatedTokenAccount(provider.connection, payer, mint, donor.publicKey);
+    await mintTo(provider.connection, payer, mint, donorTokenAccount, payer, 2000000);
+
+    const { campaign, campaignVault } = await createCampaign(
+      10000000,
+      Math.floor(Date.now() / 1000) + 5,
+      [mustahik.publicKey],
+      [10000]
+    );
+    const first = await donateToCampaign(campaign, campaignVault, payer, payerTokenAccount, 1000000);
+    const second = await donateToCampaign(campaign, campaignVault, donor, donorTokenAccount, 2000000);
+    // Tokens sent straight to the escrow are shared out with the refunds
+    await transfer(provider.connection, payer, payerTokenAccount, campaignVault, payer, 1);
+
+    await new Promise((resolve) => setTimeout(resolve, 6000));
+    const refund = async (donorKeypair: Keypair, contribution: PublicKey, tokenAccount: PublicKey) => {
+      const before = await getAccount(provider.connection, tokenAccount);
+      await program.methods
+        .refundContribution()
+        .accounts({
+          campaign,
+          campaignVault,
+          contribution,
+          donor: donorKeypair.publicKey,
+          donorTokenAccount: tokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([donorKeypair])
+        .rpc();
+      const after = await getAccount(provider.connection, tokenAccount);
+      return (after.amount - before.amount).toString();
+    };
+
+    // 1000000 * 3000001 / 3000000 rounds down; the last donor out gets what is left
+    assert.equal(await refund(payer, first.contribution, payerTokenAccount), "1000000");
+    assert.equal(await refund(donor, second.contribution, donorTokenAccount), "2000001");
+    const vault = await getAccount(provider.connection, campaignVault);
+    assert.equal(vault.amount.toString(), "0");
+
+    try {
+      await refund(donor, second.contribution, donorTokenAccount);
+      assert.fail("Expected error for a second refund");
+    } catch (error) {
+      assert.include(error.message, "Contribution is not refundable");
+    }
+  });
+
+  it("Splits a payment across funds with a receipt per part", async () => {
+    const [zakatFund] = PublicKey.findProgramAddressSync([Buffer.from("fund"), Buffer.from([1])], program.programId);
+    const [waqfFund] = PublicKey.findProgramAddressSync([Buffer.from("fund"), Buffer.from([2])], program.programId);
+    await program.methods
+      .createFund(2, "Waqf", 25, { anytime: {} }) // 2.5% fee
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        fund: waqfFund,
+        mint,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([amil])
+      .rpc();
+
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+    const receipts = [receiptPda(payer.publicKey, paymentId, 0), receiptPda(payer.publicKey, paymentId, 1)];
+    const split = async (secondFund = waqfFund, vault = programTokenAccount) => {
+      await program.methods
+        .collectSplit(
+          [
+            { fund: zakatFund, amount: new anchor.BN(1000000) },
+            { fund: secondFund, amount: new anchor.BN(400000) },
+          ],
+          50,
+          new anchor.BN(1340000),
+          paymentId
+        )
+        .accounts({
+          state: stateAccount,
+          payer: payer.publicKey,
+          payerTokenAccount: payerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount: amilTokenAccount,
+          payment,
+          hijriCalendar,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .remainingAccounts([
+          { pubkey: zakatFund, isWritable: true, isSigner: false },
+          { pubkey: receipts[0], isWritable: true, isSigner: false },
+          { pubkey: secondFund, isWritable: true, isSigner: false },
+          { pubkey: receipts[1], isWritable: true, isSigner: false },
+        ])
+        .signers([payer])
+        .rpc();
+    };
+
+    // Every part's fund must hold the vault's token
+    const otherMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+    const [otherFund] = PublicKey.findProgramAddressSync([Buffer.from("fund"), Buffer.from([3])], program.programId);
+    await program.methods
+      .createFund(3, "Sadaqah", 25, { anytime: {} })
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        fund: otherFund,
+        mint: otherMint,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([amil])
+      .rpc();
+    try {
+      await split(otherFund);
+      assert.fail("Expected error for a fund in another mint");
+    } catch (error) {
+      assert.include(error.message, "Invalid token account");
+    }
+    try {
+      await split(waqfFund, payerTokenAccount);
+      assert.fail("Expected error for a vault not owned by the state PDA");
+    } catch (error) {
+      assert.include(error.message, "ConstraintTokenOwner");
+    }
+
+    await split();
+    const first = await program.account.receipt.fetch(receipts[0]);
+    assert.ok(first.fund.equals(zakatFund));
+    assert.equal(first.amount.toString(), "950000");
+    const second = await program.account.receipt.fetch(receipts[1]);
+    assert.ok(second.fund.equals(waqfFund));
+    assert.equal(second.index, 1);
+    assert.equal(second.amount.toString(), "390000");
+    assert.equal(second.amilFee.toString(), "10000");
+    const marker = await program.account.paymentMarker.fetch(payment);
+    assert.ok(marker.receipt.equals(receipts[0]));
+
+    try {
+      await split();
+      assert.fail("Expected error for duplicate payment id");
+    } catch (error) {
+      assert.include(error.message, "already in use");
+    }
+
+    // Parts are refunded separately, against their own fund
+    await program.methods
+      .refundZakat()
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        receipt: receipts[1],
+        payerTokenAccount: payerTokenAccount,
+        programTokenAccount: programTokenAccount,
+        amilTokenAccount: amilTokenAccount,
+        agent: null,
+        profile: null,
+        fund: waqfFund,
+        invoice: null,
+        obligation: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+    const waqf = await program.account.fund.fetch(waqfFund);
+    assert.equal(waqf.totalCollected.toString(), "0");
+    assert.equal(waqf.totalAmilFeesCollected.toString(), "0");
+  });
+
+  it("Accepts integration collections only from the registered program", async () => {
+    // A keypair stands in for the protocol's authority PDA
+    const protocolAuthority = Keypair.generate();
+    const protocolProgram = Keypair.generate().publicKey;
+    const [integration] = PublicKey.findProgramAddressSync(
+      [Buffer.from("integration"), protocolAuthority.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .registerIntegration(protocolAuthority.publicKey, protocolProgram, "Example Vault")
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        integration,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([amil])
+      .rpc();
+    await approve(provider.connection, payer, payerTokenAccount, protocolAuthority.publicKey, payer, 1000000);
+
+    const collectFor = (vault = programTokenAccount) => {
+      const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+      const [payment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+        program.programId
+      );
+      return program.methods
+        .collectZakatFor(new anchor.BN(1000000), 25, paymentId)
+        .accounts({
+          state: stateAccount,
+          integration,
+          protocolAuthority: protocolAuthority.publicKey,
+          user: payer.publicKey,
+          userTokenAccount: payerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount,
+          payer: payer.publicKey,
+          receipt: receiptPda(payer.publicKey, paymentId),
+          payment,
+          hijriCalendar,
+          instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([protocolAuthority, payer])
+        .rpc();
+    };
+
+    // Collections must go to the state PDA's associated token account
+    try {
+      await collectFor(payerTokenAccount);
+      assert.fail("Expected error for a vault other than the state PDA's");
+    } catch (error) {
+      assert.include(error.message, "ConstraintAssociated");
+    }
+
+    // Signing with the authority alone is not enough outside the protocol's program
+    try {
+      await collectFor();
+      assert.fail("Expected error for a direct call");
+    } catch (error) {
+      assert.include(error.message, "Collection was not invoked by the integration's registered program");
+    }
+
+    await program.methods
+      .setIntegrationActive(false)
+      .accounts({ state: stateAccount, amil: amil.publicKey, integration })
+      .signers([amil])
+      .rpc();
+    try {
+      await collectFor();
+      assert.fail("Expected error for a paused integration");
+    } catch (error) {
+      assert.include(error.message, "Protocol integration is inactive");
+    }
+    const integrationAccount = await program.account.protocolIntegration.fetch(integration);
+    assert.ok(integrationAccount.programId.equals(protocolProgram));
+    assert.equal(integrationAccount.totalCollected.toString(), "0");
+  });
+
+  it("Collects a payroll with a receipt and profile credit per employee", async () => {
+    const employees = [Keypair.generate(), Keypair.generate()];
+    const profiles = [];
+    for (const employee of employees) {
+      const signature = await provider.connection.requestAirdrop(employee.publicKey, LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(signature);
+      const [profile] = PublicKey.findProgramAddressSync(
+        [Buffer.from("muzakki"), employee.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .registerMuzakki(new anchor.BN(Math.floor(Date.now() / 1000) - 86400))
+        .accounts({
+          state: stateAccount,
+          owner: employee.publicKey,
+          profile,
+          hijriCalendar,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([employee])
+        .rpc();
+      profiles.push(profile);
+    }
+
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const [payment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+      program.programId
+    );
+    const receipts = [receiptPda(payer.publicKey, paymentId, 0), receiptPda(payer.publicKey, paymentId, 1)];
+
+    // Lamports sent to a receipt address ahead of time do not block the payroll
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: provider.wallet.publicKey,
+          toPubkey: receipts[1],
+          lamports: 1000,
+        })
+      )
+    );
+
+    const collect = (vault: PublicKey) =>
+      program.methods
+        .collectPayroll([new anchor.BN(1000000), new anchor.BN(2000000)], 25, paymentId)
+        .accounts({
+          state: stateAccount,
+          employer: payer.publicKey,
+          employerTokenAccount: payerTokenAccount,
+          programTokenAccount: vault,
+          amilTokenAccount,
+          payment,
+          hijriCalendar,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .remainingAccounts(
+          profiles.flatMap((profile, i) => [
+            { pubkey: profile, isWritable: true, isSigner: false },
+            { pubkey: receipts[i], isWritable: true, isSigner: false },
+          ])
+        )
+        .signers([payer])
+        .rpc();
+
+    // An employer keeping the funds in their own account gets no receipts
+    try {
+      await collect(payerTokenAccount);
+      assert.fail("Expected error for a vault not owned by the state PDA");
+    } catch (error) {
+      assert.include(error.message, "ConstraintTokenOwner");
+    }
+    await collect(programTokenAccount);
+
+    const expected = ["975000", "1950000"];
+    for (let i = 0; i < employees.length; i++) {
+      const receiptAccount = await program.account.receipt.fetch(receipts[i]);
+      assert.ok(receiptAccount.muzakki.equals(employees[i].publicKey));
+      assert.ok(receiptAccount.profileCredited);
+      assert.equal(receiptAccount.amount.toString(), expected[i]);
+      const profileAccount = await program.account.muzakkiProfile.fetch(profiles[i]);
+      assert.equal(profileAccount.totalPaid.toString(), expected[i]);
+    }
+
+    // A refund takes the payment back off the employee's profile
+    await program.methods
+      .refundZakat()
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        receipt: receipts[1],
+        payerTokenAccount,
+        programTokenAccount,
+        amilTokenAccount,
+        agent: null,
+        profile: profiles[1],
+        fund: null,
+        invoice: null,
+        obligation: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+    const profileAccount = await program.account.muzakkiProfile.fetch(profiles[1]);
+    assert.equal(profileAccount.totalPaid.toString(), "0");
+  });
+
+  it("Attributes payments to an agent and claws back commission on refund", async () => {
+    const agentWallet = Keypair.generate();
+    const [agent] = PublicKey.findProgramAddressSync(
+      [Buffer.from("agent"), agentWallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const agentTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      agentWallet.publicKey
+    );
+    await program.methods
+      .registerAgent(agentWallet.publicKey, "UPZ Masjid Al-Ikhlas", 2000)
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        agent,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([amil])
+      .rpc();
+
+    const collect = (paymentId: number[], feeAccount: PublicKey) =>
+      program.methods
+        .collectZakat(new anchor.BN(1000000), 25, null, paymentId)
+        .accounts({
+          state: stateAccount,
+          payer: payer.publicKey,
+          payerTokenAccount,
+          programTokenAccount,
+          amilTokenAccount: feeAccount,
+          receipt: receiptPda(payer.publicKey, paymentId),
+          payment: PublicKey.findProgramAddressSync(
+            [Buffer.from("payment"), payer.publicKey.toBuffer(), Buffer.from(paymentId)],
+            program.programId
+          )[0],
+          hijriCalendar,
+          agent,
+          fund: null,
+          obligation: null,
+          profile: null,
+          pool: null,
+          poolVault: null,
+          donorMatch: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+
+    // Sending the amil fee to the payer's own account would still accrue
+    // commission on it
+    try {
+      await collect(Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16)), payerTokenAccount);
+      assert.fail("Expected error for a fee account not owned by the amil");
+    } catch (error) {
+      assert.include(error.message, "Invalid token account");
+    }
+
+    const paymentId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const receipt = receiptPda(payer.publicKey, paymentId);
+    await collect(paymentId, amilTokenAccount);
+
+    // 20% of the 25000 amil fee
+    let agentAccount = await program.account.collectionAgent.fetch(agent);
+    assert.equal(agentAccount.totalCollected.toString(), "975000");
+    assert.equal(agentAccount.totalAmilFees.toString(), "25000");
+    assert.equal(agentAccount.commissionAccrued.toString(), "5000");
+    const receiptAccount = await program.account.receipt.fetch(receipt);
+    assert.ok(receiptAccount.agent.equals(agent));
+    assert.equal(receiptAccount.agentCommission.toString(), "5000");
+
+    await program.methods
+      .payAgentCommission(new anchor.BN(5000))
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        agent,
+        amilTokenAccount,
+        agentTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+
+    let updated = null;
+    const listener = program.addEventListener("AgentUpdated", (event) => {
+      updated = event;
+    });
+    await program.methods
+      .updateAgent(1000, false)
+      .accounts({ state: stateAccount, amil: amil.publicKey, agent })
+      .signers([amil])
+      .rpc();
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    await program.removeEventListener(listener);
+    assert.ok(updated.agent.equals(agent));
+    assert.equal(updated.commissionBps, 1000);
+    assert.equal(updated.isActive, false);
+
+    await program.methods
+      .refundZakat()
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        receipt,
+        payerTokenAccount,
+        programTokenAccount,
+        amilTokenAccount,
+        agent,
+        profile: null,
+        fund: null,
+        invoice: null,
+        obligation: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+
+    // The paid commission is now owed back and blocks further payouts
+    agentAccount = await program.account.collectionAgent.fetch(agent);
+    assert.equal(agentAccount.commissionAccrued.toString(), "0");
+    assert.equal(agentAccount.commissionPaid.toString(), "5000");
+    try {
+      await program.methods
+        .payAgentCommission(new anchor.BN(1))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          agent,
+          amilTokenAccount,
+          agentTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([amil])
+        .rpc();
+      assert.fail("Expected error for commission owed back");
+    } catch (error) {
+      assert.include(error.message, "Insufficient funds");
+    }
+
+    const amilBefore = await getAccount(provider.connection, amilTokenAccount);
+    await program.methods
+      .repayAgentCommission(new anchor.BN(5000))
+      .accounts({
+        state: stateAccount,
+        wallet: agentWallet.publicKey,
+        agent,
+        agentTokenAccount,
+        amilTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([agentWallet])
+      .rpc();
+    const amilAfter = await getAccount(provider.connection, amilTokenAccount);
+    assert.equal((amilAfter.amount - amilBefore.amount).toString(), "5000");
+    agentAccount = await program.account.collectionAgent.fetch(agent);
+    assert.equal(agentAccount.commissionPaid.toString(), "0");
+  });
+
+  it("Activates a mustahik through application, survey and approval", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    for (const [officer, role] of [
+      [surveyor, { surveyor: {} }],
+      [approver, { approver: {} }],
+    ] as const) {
+      await program.methods
+        .registerOfficer(officer.publicKey, role)
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          officer: officerPda(officer.publicKey),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([amil])
+        .rpc();
+    }
+
+    const salt = Buffer.alloc(32, 7);
+    const identityHash = Array.from(
+      createHash("sha256").update(Buffer.concat([salt, Buffer.from("3171010101010001")])).digest()
+    );
+    const signature = await provider.connection.requestAirdrop(mustahik.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    await program.methods
+      .submitApplication(mustahik.publicKey, { fakir: {} }, identityHash, "ipfs://mustahik-record")
+      .accounts({
+        state: stateAccount,
+        applicant: mustahik.publicKey,
+        officer: null,
+        mustahik: mustahikAccount,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([mustahik])
+      .rpc();
+
+    // The surveyor hashes the NIK they checked in the field with the record's salt
+    const survey = (surveyedHash: number[]) =>
+      program.methods
+        .recordSurvey(surveyedHash, Array(32).fill(0), Array(32).fill(1), true)
+        .accounts({
+          state: stateAccount,
+          surveyor: surveyor.publicKey,
+          officer: officerPda(surveyor.publicKey),
+          mustahik: mustahikAccount,
+        })
+        .signers([surveyor])
+        .rpc();
+    try {
+      await survey(
+        Array.from(createHash("sha256").update(Buffer.concat([salt, Buffer.from("3171010101010002")])).digest())
+      );
+      assert.fail("Expected error for a different NIK");
+    } catch (error) {
+      assert.include(error.message, "Identity hash does not match the application");
+    }
+    await survey(identityHash);
+
+    // Only approval adds to the count
+    const countBefore = (await program.account.zakaChainState.fetch(stateAccount)).mustahikCount;
+    await program.methods
+      .reviewApplication(true)
+      .accounts({
+        state: stateAccount,
+        approver: approver.publicKey,
+        officer: officerPda(approver.publicKey),
+        mustahik: mustahikAccount,
+        hijriCalendar,
+        coreProgram: null,
+        registryIdentity: null,
+        registryEnrolment: null,
+        registryInstitution: null,
+        systemProgram: null,
+      })
+      .signers([approver])
+      .rpc();
+
+    // Verify mustahik account
+    const account = await program.account.mustahik.fetch(mustahikAccount);
+    assert.ok(account.wallet.equals(mustahik.publicKey));
+    assert.deepEqual(account.status, { active: {} });
+    assert.ok(account.surveyor.equals(surveyor.publicKey));
+    assert.ok(account.approver.equals(approver.publicKey));
+    assert.ok(account.eligibleUntil.gt(account.approvedAt));
+    const state = await program.account.zakaChainState.fetch(stateAccount);
+    assert.equal(state.mustahikCount.toNumber(), countBefore.toNumber() + 1);
+
+    // An auditor checks a revealed identity by simulation
+    const verified = await program.methods
+      .verifyIdentity("3171010101010001", Array.from(salt))
+      .accounts({ mustahik: mustahikAccount })
+      .view();
+    assert.isTrue(verified);
+  });
+
+  it("Lets a rejected candidate re-apply and counts only approved mustahiks", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    const mustahikCount = async () =>
+      (await program.account.zakaChainState.fetch(stateAccount)).mustahikCount.toNumber();
+    const apply = async (candidate: Keypair, account: PublicKey, identityHash = Array(32).fill(3)) =>
+      program.methods
+        .submitApplication(candidate.publicKey, { miskin: {} }, identityHash, "")
+        .accounts({
+          state: stateAccount,
+          applicant: candidate.publicKey,
+          officer: null,
+          mustahik: account,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([candidate])
+        .rpc();
+    const surveyAndReview = async (account: PublicKey, approved: boolean) => {
+      await program.methods
+        .recordSurvey(Array(32).fill(3), Array(32).fill(0), Array(32).fill(2), approved)
+        .accounts({
+          state: stateAccount,
+          surveyor: surveyor.publicKey,
+          officer: officerPda(surveyor.publicKey),
+          mustahik: account,
+        })
+        .signers([surveyor])
+        .rpc();
+      await program.methods
+        .reviewApplication(approved)
+        .accounts({
+          state: stateAccount,
+          approver: approver.publicKey,
+          officer: officerPda(approver.publicKey),
+          mustahik: account,
+          hijriCalendar,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+          systemProgram: null,
+        })
+        .signers([approver])
+        .rpc();
+    };
+    const remove = (candidate: Keypair, account: PublicKey) =>
+      program.methods
+        .removeMustahik(candidate.publicKey)
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahik: account,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+        })
+        .signers([amil])
+        .rpc();
+
+    const candidates = [Keypair.generate(), Keypair.generate()];
+    const accounts = [];
+    for (const candidate of candidates) {
+      const signature = await provider.connection.requestAirdrop(candidate.publicKey, LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(signature);
+      accounts.push(
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("mustahik"), stateAccount.toBuffer(), candidate.publicKey.toBuffer()],
+          program.programId
+        )[0]
+      );
+    }
+    const count = await mustahikCount();
+
+    await apply(candidates[0], accounts[0]);
+    assert.equal(await mustahikCount(), count);
+    // A pending application cannot be replaced
+    try {
+      await apply(candidates[0], accounts[0]);
+      assert.fail("Expected error for a pending application");
+    } catch (error) {
+      assert.include(error.message, "Mustahik is not in the required status");
+    }
+    await surveyAndReview(accounts[0], false);
+    assert.equal(await mustahikCount(), count);
+
+    // A re-application keeps the identity the first application committed to
+    try {
+      await apply(candidates[0], accounts[0], Array(32).fill(4));
+      assert.fail("Expected error for a different identity hash");
+    } catch (error) {
+      assert.include(error.message, "Identity hash does not match the application");
+    }
+
+    // After a rejection the candidate applies again and is approved
+    await apply(candidates[0], accounts[0]);
+    const account = await program.account.mustahik.fetch(accounts[0]);
+    assert.deepEqual(account.status, { applied: {} });
+    assert.equal(account.approvedAt.toNumber(), 0);
+    await surveyAndReview(accounts[0], true);
+    assert.equal(await mustahikCount(), count + 1);
+
+    // Removing a rejected candidate leaves the count alone
+    await apply(candidates[1], accounts[1]);
+    await surveyAndReview(accounts[1], false);
+    await remove(candidates[1], accounts[1]);
+    assert.equal(await mustahikCount(), count + 1);
+
+    await remove(candidates[0], accounts[0]);
+    assert.equal(await mustahikCount(), count);
+  });
+
+  it("Grants eligibility for a Hijri year and rejects on a negative re-verification", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    const candidate = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(candidate.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const [account] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mustahik"), stateAccount.toBuffer(), candidate.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .submitApplication(candidate.publicKey, { gharimin: {} }, Array(32).fill(4), "")
+      .accounts({
+        state: stateAccount,
+        applicant: candidate.publicKey,
+        officer: null,
+        mustahik: account,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([candidate])
+      .rpc();
+    await program.methods
+      .recordSurvey(Array(32).fill(4), Array(32).fill(0), Array(32).fill(5), true)
+      .accounts({
+        state: stateAccount,
+        surveyor: surveyor.publicKey,
+        officer: officerPda(surveyor.publicKey),
+        mustahik: account,
+      })
+      .signers([surveyor])
+      .rpc();
+    await program.methods
+      .reviewApplication(true)
+      .accounts({
+        state: stateAccount,
+        approver: approver.publicKey,
+        officer: officerPda(approver.publicKey),
+        mustahik: account,
+        hijriCalendar,
+        coreProgram: null,
+        registryIdentity: null,
+        registryEnrolment: null,
+        registryInstitution: null,
+        systemProgram: null,
+      })
+      .signers([approver])
+      .rpc();
+
+    // Eligible until the start of the same Hijri date a year on: 354 or 355 days
+    let mustahikAccount = await program.account.mustahik.fetch(account);
+    const approvedDay = Math.floor(mustahikAccount.approvedAt.toNumber() / 86400);
+    const eligibleDays = mustahikAccount.eligibleUntil.toNumber() / 86400 - approvedDay;
+    assert.include([354, 355], eligibleDays);
+
+    const reverify = (eligible: boolean) =>
+      program.methods
+        .reverifyMustahik(Array(32).fill(6), eligible)
+        .accounts({
+          state: stateAccount,
+          surveyor: surveyor.publicKey,
+          officer: officerPda(surveyor.publicKey),
+          mustahik: account,
+          hijriCalendar,
+        })
+        .signers([surveyor])
+        .rpc();
+
+    await reverify(true);
+    mustahikAccount = await program.account.mustahik.fetch(account);
+    assert.deepEqual(mustahikAccount.status, { active: {} });
+    assert.include([354, 355], mustahikAccount.eligibleUntil.toNumber() / 86400 - approvedDay);
+
+    const countBefore = (await program.account.zakaChainState.fetch(stateAccount)).mustahikCount.toNumber();
+    await reverify(false);
+    mustahikAccount = await program.account.mustahik.fetch(account);
+    assert.deepEqual(mustahikAccount.status, { rejected: {} });
+    assert.ok(mustahikAccount.eligibleUntil.lte(mustahikAccount.surveyedAt));
+    const countAfter = (await program.account.zakaChainState.fetch(stateAccount)).mustahikCount.toNumber();
+    assert.equal(countAfter, countBefore - 1);
+
+    // A rejected mustahik is no longer re-verified
+    try {
+      await reverify(true);
+      assert.fail("Expected error for re-verifying a rejected mustahik");
+    } catch (error) {
+      assert.include(error.message, "Mustahik is not in the required status");
+    }
+  });
+
+  it("Distributes Zakat to a mustahik", async () => {
+    const distributionAmount = new anchor.BN(100000); // 0.1 token
+    const distribute = (recipientTokenAccount: PublicKey) =>
+      program.methods
+        .distributeToMustahik(distributionAmount)
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahik: mustahik.publicKey,
+          mustahikAccount,
+          programTokenAccount: programTokenAccount,
+          mustahikTokenAccount: recipientTokenAccount,
+          hijriCalendar,
+          overrideApprover: null,
+          overrideOfficer: null,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([amil])
+        .rpc();
+
+    // A payment charged to this mustahik cannot go to someone else's account
+    try {
+      await distribute(amilTokenAccount);
+      assert.fail("Expected error for a token account the mustahik does not own");
+    } catch (error) {
+      assert.include(error.message, "Invalid token account");
+    }
+
+    await distribute(mustahikTokenAccount);
+
+    // Verify balances
+    const mustahikTokenBalance = await getAccount(provider.connection, mustahikTokenAccount);
+    assert.equal(mustahikTokenBalance.amount.toString(), distributionAmount.toString());
+
+    // Verify state
+    const state = await program.account.zakaChainState.fetch(stateAccount);
+    assert.equal(state.totalZakatDistributed.toString(), distributionAmount.toString());
+
+    // Verify mustahik totals
+    const account = await program.account.mustahik.fetch(mustahikAccount);
+    assert.equal(account.totalReceived.toString(), distributionAmount.toString());
+    assert.equal(account.monthReceived.toString(), distributionAmount.toString());
+    assert.equal(account.paymentCount.toNumber(), 1);
+  });
+
+  it("Rejects a distribution above the mustahik's cap without a second approver", async () => {
+    await program.methods
+      .setMustahikCaps({ perPayment: new anchor.BN(50000), perMonth: new anchor.BN(0), perYear: new anchor.BN(0) })
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        mustahik: mustahikAccount,
+      })
+      .signers([amil])
+      .rpc();
+
+    try {
+      await program.methods
+        .distributeToMustahik(new anchor.BN(100000))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahik: mustahik.publicKey,
+          mustahikAccount,
+          programTokenAccount: programTokenAccount,
+          mustahikTokenAccount: mustahikTokenAccount,
+          hijriCalendar,
+          overrideApprover: null,
+          overrideOfficer: null,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([amil])
+        .rpc();
+      assert.fail("Expected error for exceeding the cap");
+    } catch (error) {
+      assert.include(error.message, "Disbursement cap exceeded");
+    }
+  });
+
+  it("Overrides a cap with a second approver's own officer account", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    const distribute = (overrideOfficer: PublicKey) =>
+      program.methods
+        .distributeToMustahik(new anchor.BN(100000))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahik: mustahik.publicKey,
+          mustahikAccount,
+          programTokenAccount: programTokenAccount,
+          mustahikTokenAccount: mustahikTokenAccount,
+          hijriCalendar,
+          overrideApprover: approver.publicKey,
+          overrideOfficer,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([amil, approver])
+        .rpc();
+
+    // Another officer's account cannot vouch for the signer
+    try {
+      await distribute(officerPda(surveyor.publicKey));
+      assert.fail("Expected error for another officer's account");
+    } catch (error) {
+      assert.include(error.message, "ConstraintSeeds");
+    }
+
+    const before = await program.account.mustahik.fetch(mustahikAccount);
+    await distribute(officerPda(approver.publicKey));
+    const after = await program.account.mustahik.fetch(mustahikAccount);
+    assert.equal(after.totalReceived.sub(before.totalReceived).toString(), "100000");
+  });
+
+  it("Distributes a batch atomically with an event per payment", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    const recipients = [];
+    for (const [index, asnaf] of [{ fakir: {} }, { miskin: {} }].entries()) {
+      const candidate = Keypair.generate();
+      const signature = await provider.connection.requestAirdrop(candidate.publicKey, LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(signature);
+      const [account] = PublicKey.findProgramAddressSync(
+        [Buffer.from("mustahik"), stateAccount.toBuffer(), candidate.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .submitApplication(candidate.publicKey, asnaf, Array(32).fill(20 + index), "")
+        .accounts({
+          state: stateAccount,
+          applicant: candidate.publicKey,
+          officer: null,
+          mustahik: account,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([candidate])
+        .rpc();
+      await program.methods
+        .recordSurvey(Array(32).fill(20 + index), Array(32).fill(0), Array(32).fill(30 + index), true)
+        .accounts({
+          state: stateAccount,
+          surveyor: surveyor.publicKey,
+          officer: officerPda(surveyor.publicKey),
+          mustahik: account,
+        })
+        .signers([surveyor])
+        .rpc();
+      await program.methods
+        .reviewApplication(true)
+        .accounts({
+          state: stateAccount,
+          approver: approver.publicKey,
+          officer: officerPda(approver.publicKey),
+          mustahik: account,
+          hijriCalendar,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+          systemProgram: null,
+        })
+        .signers([approver])
+        .rpc();
+      const tokenAccount = await createAssociatedTokenAccount(provider.connection, payer, mint, candidate.publicKey);
+      recipients.push({ wallet: candidate.publicKey, account, tokenAccount });
+    }
+    // The mustahik from earlier tests has a per-payment cap of 50000
+    const capped = { wallet: mustahik.publicKey, account: mustahikAccount, tokenAccount: mustahikTokenAccount };
+
+    const distribute = (payments: { recipient: (typeof recipients)[number]; amount: number }[]) =>
+      program.methods
+        .distributeBatch(payments.map((payment) => new anchor.BN(payment.amount)))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          programTokenAccount,
+          hijriCalendar,
+          coreProgram: null,
+          registryInstitution: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          payments.flatMap(({ recipient }) => [
+            { pubkey: recipient.account, isWritable: true, isSigner: false },
+            { pubkey: recipient.tokenAccount, isWritable: true, isSigner: false },
+          ])
+        )
+        .signers([amil])
+        .rpc();
+    const balance = async (tokenAccount: PublicKey) =>
+      (await getAccount(provider.connection, tokenAccount)).amount.toString();
+
+    // A leg over a cap fails the batch, including the legs before it
+    try {
+      await distribute([
+        { recipient: recipients[0], amount: 10000 },
+        { recipient: capped, amount: 60000 },
+      ]);
+      assert.fail("Expected error for a leg over the cap");
+    } catch (error) {
+      assert.include(error.message, "Disbursement cap exceeded");
+    }
+    assert.equal(await balance(recipients[0].tokenAccount), "0");
+
+    // A mustahik can appear only once per batch
+    try {
+      await distribute([
+        { recipient: recipients[0], amount: 10000 },
+        { recipient: recipients[0], amount: 10000 },
+      ]);
+      assert.fail("Expected error for a repeated mustahik");
+    } catch (error) {
+      assert.include(error.message, "Invalid distribution batch");
+    }
+    assert.equal(await balance(recipients[0].tokenAccount), "0");
+
+    const events = [];
+    const listener = program.addEventListener("ZakatDistributed", (event) => {
+      events.push(event);
+    });
+    const stateBefore = await program.account.zakaChainState.fetch(stateAccount);
+    await distribute([
+      { recipient: recipients[0], amount: 10000 },
+      { recipient: recipients[1], amount: 20000 },
+    ]);
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    await program.removeEventListener(listener);
+
+    assert.equal(await balance(recipients[0].tokenAccount), "10000");
+    assert.equal(await balance(recipients[1].tokenAccount), "20000");
+    assert.equal(events.length, 2);
+    assert.ok(events[0].mustahik.equals(recipients[0].wallet));
+    assert.equal(events[0].amount.toNumber(), 10000);
+    assert.ok(events[1].mustahik.equals(recipients[1].wallet));
+    assert.equal(events[1].amount.toNumber(), 20000);
+    const stateAfter = await program.account.zakaChainState.fetch(stateAccount);
+    assert.equal(stateAfter.totalZakatDistributed.sub(stateBefore.totalZakatDistributed).toNumber(), 30000);
+    for (const [index, amount] of [10000, 20000].entries()) {
+      const account = await program.account.mustahik.fetch(recipients[index].account);
+      assert.equal(account.totalReceived.toNumber(), amount);
+      assert.equal(account.paymentCount.toNumber(), 1);
+    }
+  });
+
+  it("Allocates a claim, keeps the later expiry and reclaims after it lapses", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    // A fresh mustahik without a token account, so claiming creates it
+    const candidate = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(candidate.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const [account] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mustahik"), stateAccount.toBuffer(), candidate.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .submitApplication(candidate.publicKey, { fakir: {} }, Array(32).fill(7), "")
+      .accounts({
+        state: stateAccount,
+        applicant: candidate.publicKey,
+        officer: null,
+        mustahik: account,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([candidate])
+      .rpc();
+    await program.methods
+      .recordSurvey(Array(32).fill(7), Array(32).fill(0), Array(32).fill(8), true)
+      .accounts({
+        state: stateAccount,
+        surveyor: surveyor.publicKey,
+        officer: officerPda(surveyor.publicKey),
+        mustahik: account,
+      })
+      .signers([surveyor])
+      .rpc();
+    await program.methods
+      .reviewApplication(true)
+      .accounts({
+        state: stateAccount,
+        approver: approver.publicKey,
+        officer: officerPda(approver.publicKey),
+        mustahik: account,
+        hijriCalendar,
+        coreProgram: null,
+        registryIdentity: null,
+        registryEnrolment: null,
+        registryInstitution: null,
+        systemProgram: null,
+      })
+      .signers([approver])
+      .rpc();
+
+    const [claim] = PublicKey.findProgramAddressSync([Buffer.from("claim"), account.toBuffer()], program.programId);
+    const [claimVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("claim_vault"), claim.toBuffer()],
+      program.programId
+    );
+    const allocate = (amount: number, expiresAt: number) =>
+      program.methods
+        .allocateClaim(new anchor.BN(amount), new anchor.BN(expiresAt))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahikAccount: account,
+          claim,
+          mint,
+          claimVault,
+          programTokenAccount,
+          hijriCalendar,
+          overrideApprover: null,
+          overrideOfficer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([amil])
+        .rpc();
+    const reclaim = () =>
+      program.methods
+        .reclaimAllocation()
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahikAccount: account,
+          claim,
+          claimVault,
+          programTokenAccount,
+          hijriCalendar,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([amil])
+        .rpc();
+
+    // A top-up with an earlier expiry leaves the escrowed amount's window alone
+    const now = Math.floor(Date.now() / 1000);
+    await allocate(30000, now + 3600);
+    await allocate(20000, now + 60);
+    let claimAccount = await program.account.claim.fetch(claim);
+    assert.equal(claimAccount.amount.toNumber(), 50000);
+    assert.equal(claimAccount.expiresAt.toNumber(), now + 3600);
+    // Allocations count against the month cap before they are claimed
+    let mustahikState = await program.account.mustahik.fetch(account);
+    assert.equal(mustahikState.monthReceived.toNumber(), 50000);
+    assert.equal(mustahikState.totalReceived.toNumber(), 0);
+
+    // Nothing can be reclaimed before the claim expires
+    try {
+      await reclaim();
+      assert.fail("Expected error for reclaiming an open claim");
+    } catch (error) {
+      assert.include(error.message, "Claim has not expired yet");
+    }
+
+    const candidateTokenAccount = getAssociatedTokenAddressSync(mint, candidate.publicKey);
+    assert.isNull(await provider.connection.getAccountInfo(candidateTokenAccount));
+    await program.methods
+      .claimAllocation()
+      .accounts({
+        state: stateAccount,
+        mustahik: candidate.publicKey,
+        mustahikAccount: account,
+        claim,
+        claimVault,
+        mint,
+        mustahikTokenAccount: candidateTokenAccount,
+        hijriCalendar,
+        coreProgram: null,
+        registryIdentity: null,
+        registryEnrolment: null,
+        registryInstitution: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([candidate])
+      .rpc();
+    assert.equal((await getAccount(provider.connection, candidateTokenAccount)).amount.toString(), "50000");
+    claimAccount = await program.account.claim.fetch(claim);
+    assert.equal(claimAccount.amount.toNumber(), 0);
+    assert.equal(claimAccount.totalClaimed.toNumber(), 50000);
+    mustahikState = await program.account.mustahik.fetch(account);
+    assert.equal(mustahikState.totalReceived.toNumber(), 50000);
+    assert.equal(mustahikState.monthReceived.toNumber(), 50000);
+    assert.equal(mustahikState.paymentCount.toNumber(), 1);
+
+    // Once claimed, a new allocation sets its own expiry and returns to the
+    // vault when it lapses unclaimed
+    const vaultBefore = await getAccount(provider.connection, programTokenAccount);
+    await allocate(40000, Math.floor(Date.now() / 1000) + 2);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    await reclaim();
+    const vaultAfter = await getAccount(provider.connection, programTokenAccount);
+    assert.equal(vaultAfter.amount.toString(), vaultBefore.amount.toString());
+    claimAccount = await program.account.claim.fetch(claim);
+    assert.equal(claimAccount.amount.toNumber(), 0);
+    assert.equal(claimAccount.totalAllocated.toNumber(), 90000);
+    // The lapsed allocation no longer counts against the caps
+    mustahikState = await program.account.mustahik.fetch(account);
+    assert.equal(mustahikState.monthReceived.toNumber(), 50000);
+  });
+
+  it("Counts outstanding allocations against the month cap", async () => {
+    const account = mustahikAccount;
+    const [claim] = PublicKey.findProgramAddressSync([Buffer.from("claim"), account.toBuffer()], program.programId);
+    const [claimVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("claim_vault"), claim.toBuffer()],
+      program.programId
+    );
+    const before = await program.account.mustahik.fetch(account);
+    const monthCap = before.monthReceived.toNumber() + 30000;
+    await program.methods
+      .setMustahikCaps({ perPayment: new anchor.BN(50000), perMonth: new anchor.BN(monthCap), perYear: new anchor.BN(0) })
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        mustahik: account,
+      })
+      .signers([amil])
+      .rpc();
+    const allocate = (amount: number) =>
+      program.methods
+        .allocateClaim(new anchor.BN(amount), new anchor.BN(Math.floor(Date.now() / 1000) + 3600))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahikAccount: account,
+          claim,
+          mint,
+          claimVault,
+          programTokenAccount,
+          hijriCalendar,
+          overrideApprover: null,
+          overrideOfficer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([amil])
+        .rpc();
+
+    await allocate(20000);
+    try {
+      await allocate(20000);
+      assert.fail("Expected error for allocations over the month cap");
+    } catch (error) {
+      assert.include(error.message, "Disbursement cap exceeded");
+    }
+    const after = await program.account.mustahik.fetch(account);
+    assert.equal(after.monthReceived.toNumber(), monthCap - 10000);
+
+    // Restore the per-payment cap later tests expect
+    await program.methods
+      .setMustahikCaps({ perPayment: new anchor.BN(50000), perMonth: new anchor.BN(0), perYear: new anchor.BN(0) })
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        mustahik: account,
+      })
+      .signers([amil])
+      .rpc();
+  });
+
+  it("Pays round leaves once against a Merkle proof and closes the round", async () => {
+    // Leaves and sorted-pair hashing as the program computes them
+    const leafHash = (index: number, wallet: PublicKey, amount: number) => {
+      const indexBytes = Buffer.alloc(4);
+      indexBytes.writeUInt32LE(index);
+      const amountBytes = Buffer.alloc(8);
+      amountBytes.writeBigUInt64LE(BigInt(amount));
+      return Buffer.from(keccak_256(Buffer.concat([indexBytes, wallet.toBuffer(), amountBytes])));
+    };
+    const hashPair = (a: Buffer, b: Buffer) =>
+      Buffer.from(keccak_256(Buffer.compare(a, b) <= 0 ? Buffer.concat([a, b]) : Buffer.concat([b, a])));
+
+    const amounts = [40000, 10000, 20000, 30000];
+    const wallets = [mustahik.publicKey, ...[1, 2, 3].map(() => Keypair.generate().publicKey)];
+    const leaves = amounts.map((amount, index) => leafHash(index, wallets[index], amount));
+    const left = hashPair(leaves[0], leaves[1]);
+    const right = hashPair(leaves[2], leaves[3]);
+    const root = hashPair(left, right);
+    const proofOfFirst = [leaves[1], right].map((node) => Array.from(node));
+    const total = amounts.reduce((sum, amount) => sum + amount, 0);
+
+    const roundCount = (await program.account.zakaChainState.fetch(stateAccount)).roundCount;
+    const [round] = PublicKey.findProgramAddressSync(
+      [Buffer.from("round"), roundCount.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [roundVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("round_vault"), round.toBuffer()],
+      program.programId
+    );
+    const expiresAt = Math.floor(Date.now() / 1000) + 20;
+    await program.methods
+      .createRound(Array.from(root), leaves.length, new anchor.BN(total), new anchor.BN(expiresAt))
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        round,
+        mint,
+        roundVault,
+        programTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([amil])
+      .rpc();
+
+    const claim = (index: number, amount: number, proof: number[][]) =>
+      program.methods
+        .claimFromRound(index, new anchor.BN(amount), proof)
+        .accounts({
+          state: stateAccount,
+          round,
+          roundVault,
+          mustahikAccount,
+          mustahikTokenAccount,
+          hijriCalendar,
+          coreProgram: null,
+          registryIdentity: null,
+          registryEnrolment: null,
+          registryInstitution: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    // A leaf cannot be claimed for a different amount or under another index
+    for (const [index, amount] of [
+      [0, 45000],
+      [1, 40000],
+    ]) {
+      try {
+        await claim(index, amount, proofOfFirst);
+        assert.fail("Expected error for a proof that does not match the leaf");
+      } catch (error) {
+        assert.include(error.message, "Invalid Merkle proof");
+      }
+    }
+
+    const balanceBefore = await getAccount(provider.connection, mustahikTokenAccount);
+    await claim(0, amounts[0], proofOfFirst);
+    const balanceAfter = await getAccount(provider.connection, mustahikTokenAccount);
+    assert.equal((balanceAfter.amount - balanceBefore.amount).toString(), amounts[0].toString());
+    let roundAccount = await program.account.round.fetch(round);
+    assert.deepEqual(Array.from(roundAccount.claimed), [0b0000_0001]);
+    assert.equal(roundAccount.claimedAmount.toNumber(), amounts[0]);
+
+    try {
+      await claim(0, amounts[0], proofOfFirst);
+      assert.fail("Expected error for claiming a leaf twice");
+    } catch (error) {
+      assert.include(error.message, "Leaf has already been claimed");
+    }
+
+    // After expiry the unclaimed leaves go back to the vault
+    await new Promise((resolve) => setTimeout(resolve, Math.max(0, (expiresAt + 2) * 1000 - Date.now())));
+    const vaultBefore = await getAccount(provider.connection, programTokenAccount);
+    await program.methods
+      .closeRound()
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        round,
+        roundVault,
+        programTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+    const vaultAfter = await getAccount(provider.connection, programTokenAccount);
+    assert.equal((vaultAfter.amount - vaultBefore.amount).toString(), (total - amounts[0]).toString());
+    roundAccount = await program.account.round.fetch(round);
+    assert.ok(roundAccount.closed);
+  });
+
+  it("Enrols an approved mustahik in the core registry and reports distributions", async () => {
+    const officerPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("officer"), wallet.toBuffer()], program.programId)[0];
+    const [institution] = PublicKey.findProgramAddressSync(
+      [Buffer.from("institution"), program.programId.toBuffer()],
+      coreProgram.programId
+    );
+
+    // Only the core authority admits an institution
+    const register = (authority: PublicKey) =>
+      coreProgram.methods
+        .registerInstitution(program.programId)
+        .accounts({
+          state: coreState,
+          institution,
+          authority,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        });
+    try {
+      await register(amil.publicKey).signers([amil]).rpc();
+      assert.fail("Expected error for registering without the core authority");
+    } catch (error) {
+      assert.include(error.message, "Unauthorized");
+    }
+    await register(provider.wallet.publicKey).rpc();
+    const institutionAccount = await coreProgram.account.institution.fetch(institution);
+    assert.ok(institutionAccount.authority.equals(stateAccount));
+
+    // The consortium's hashing service keys the NIK; institutions only see the result
+    const nik = "3273010101010009";
+    const dedupHash = createHmac("sha256", Buffer.alloc(32, 42)).update(nik).digest();
+    const [identity] = PublicKey.findProgramAddressSync(
+      [Buffer.from("identity"), dedupHash],
+      coreProgram.programId
+    );
+    const [enrolment] = PublicKey.findProgramAddressSync(
+      [Buffer.from("enrolment"), dedupHash, institution.toBuffer()],
+      coreProgram.programId
+    );
+
+    const candidate = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(candidate.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const [account] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mustahik"), stateAccount.toBuffer(), candidate.publicKey.toBuffer()],
+      program.programId
+    );
+    const salt = Buffer.alloc(32, 9);
+    const identityHash = Array.from(createHash("sha256").update(Buffer.concat([salt, Buffer.from(nik)])).digest());
+
+    // Only the institution's program can sign as its state PDA
+    try {
+      await coreProgram.methods
+        .enrolIdentity(Array.from(dedupHash))
+        .accounts({
+          identity,
+          enrolment,
+          institution,
+          authority: amil.publicKey,
+          mustahikAccount,
+          payer: amil.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([amil])
+        .rpc();
+      assert.fail("Expected error for enrolling outside the institution's program");
+    } catch (error) {
+      assert.include(error.message, "ConstraintHasOne");
+    }
+
+    await program.methods
+      .submitApplication(candidate.publicKey, { fakir: {} }, identityHash, "")
+      .accounts({
+        state: stateAccount,
+        applicant: candidate.publicKey,
+        officer: null,
+        mustahik: account,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([candidate])
+      .rpc();
+    await program.methods
+      .recordSurvey(identityHash, Array.from(dedupHash), Array(32).fill(40), true)
+      .accounts({
+        state: stateAccount,
+        surveyor: surveyor.publicKey,
+        officer: officerPda(surveyor.publicKey),
+        mustahik: account,
+      })
+      .signers([surveyor])
+      .rpc();
+    await program.methods
+      .reviewApplication(true)
+      .accounts({
+        state: stateAccount,
+        approver: approver.publicKey,
+        officer: officerPda(approver.publicKey),
+        mustahik: account,
+        hijriCalendar,
+        coreProgram: coreProgram.programId,
+        registryIdentity: identity,
+        registryEnrolment: enrolment,
+        registryInstitution: institution,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([approver])
+      .rpc();
+
+    let identityAccount = await coreProgram.account.identityRecord.fetch(identity);
+    assert.equal(identityAccount.enrolmentCount, 1);
+    const enrolmentAccount = await coreProgram.account.enrolment.fetch(enrolment);
+    assert.ok(enrolmentAccount.mustahikAccount.equals(account));
+    assert.ok(enrolmentAccount.institution.equals(institution));
+    const mustahikState = await program.account.mustahik.fetch(account);
+    assert.ok(mustahikState.enrolment.equals(enrolment));
+
+    const candidateTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      candidate.publicKey
+    );
+    const distribute = (registry: boolean) =>
+      program.methods
+        .distributeToMustahik(new anchor.BN(25000))
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          mustahik: candidate.publicKey,
+          mustahikAccount: account,
+          programTokenAccount,
+          mustahikTokenAccount: candidateTokenAccount,
+          hijriCalendar,
+          overrideApprover: null,
+          overrideOfficer: null,
+          coreProgram: registry ? coreProgram.programId : null,
+          registryIdentity: registry ? identity : null,
+          registryEnrolment: registry ? enrolment : null,
+          registryInstitution: registry ? institution : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([amil])
+        .rpc();
+
+    // An enrolled mustahik's payments cannot bypass the registry
+    try {
+      await distribute(false);
+      assert.fail("Expected error for leaving out the registry accounts");
+    } catch (error) {
+      assert.include(error.message, "Registry accounts are missing or do not match the mustahik's enrolment");
+    }
+    await distribute(true);
+    identityAccount = await coreProgram.account.identityRecord.fetch(identity);
+    assert.equal(identityAccount.totalReceived.toNumber(), 25000);
+    assert.equal((await coreProgram.account.enrolment.fetch(enrolment)).totalReceived.toNumber(), 25000);
+
+    // Removing the mustahik withdraws the enrolment; the totals stay
+    await program.methods
+      .removeMustahik(candidate.publicKey)
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        mustahik: account,
+        coreProgram: coreProgram.programId,
+        registryIdentity: identity,
+        registryEnrolment: enrolment,
+        registryInstitution: institution,
+      })
+      .signers([amil])
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(enrolment));
+    identityAccount = await coreProgram.account.identityRecord.fetch(identity);
+    assert.equal(identityAccount.enrolmentCount, 0);
+    assert.equal(identityAccount.totalReceived.toNumber(), 25000);
+  });
+
+  it("Withdraws for manual distribution", async () => {
+    const withdrawalAmount = new anchor.BN(100000); // 0.1 token
+    const description = "Emergency relief distribution";
+
+    await program.methods
+      .withdrawForManualDistribution(withdrawalAmount, description)
+      .accounts({
+        state: stateAccount,
+        amil: amil.publicKey,
+        programTokenAccount: programTokenAccount,
+        amilOperationalAccount: amilOperationalTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([amil])
+      .rpc();
+
+    // Verify balances
+    const amilOperationalBalance = await getAccount(provider.connection, amilOperationalTokenAccount);
+    assert.equal(amilOperationalBalance.amount.toString(), withdrawalAmount.toString());
+  });
+
+  it("Fails to initialize with invalid fee percentage", async () => {
+    const invalidFeePercentage = 126; // 12.6% (above max of 12.5%)
+
+    try {
+      await program.methods
+        .initialize(invalidFeePercentage)
+        .accounts({
+          state: stateAccount,
+          amil: amil.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([amil])
+        .rpc();
+      assert.fail("Expected error for invalid fee percentage");
+    } catch (error) {
+      assert.include(error.message, "Invalid fee percentage");
+    }
+  });
+
+  it("Fails to apply on behalf of a mustahik without a field officer role", async () => {
+    const unauthorizedAccount = Keypair.generate();
+    const otherMustahik = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(unauthorizedAccount.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(signature);
+    const [otherMustahikAccount] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mustahik"), stateAccount.toBuffer(), otherMustahik.publicKey.toBuffer()],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .submitApplication(otherMustahik.publicKey, { miskin: {} }, Array(32).fill(0), "")
+        .accounts({
+          state: stateAccount,
+          applicant: unauthorizedAccount.publicKey,
+          officer: null,
+          mustahik: otherMustahikAccount,
+          systemProgram: anchor.web3.SystemProgram.programId,
         })
         .signers([unauthorizedAccount])
         .rpc();